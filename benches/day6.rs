@@ -0,0 +1,22 @@
+use aoc2022::day6::{detect, detect_reader, protocol};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+fn bench_markers(c: &mut Criterion) {
+    let input = include_str!("../resources/day6part1").trim();
+    // Put a long run without any marker in front of the input so most of the
+    // stream is scanned before the marker is found.
+    let stream = "abcabcabcabc".repeat(1 << 16) + input;
+    let mut group = c.benchmark_group("day6");
+    group.bench_function("protocol", |b| b.iter(|| protocol(black_box(&stream), 14)));
+    group.bench_function("detect", |b| {
+        b.iter(|| detect(black_box(stream.bytes()), 14))
+    });
+    group.bench_function("detect_reader", |b| {
+        b.iter(|| detect_reader(black_box(stream.as_bytes()), 14).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_markers);
+criterion_main!(benches);
@@ -1,8 +1,18 @@
-use std::collections::HashSet;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    fmt::{self, Display},
+    ops::ControlFlow,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const ITEM_TYPES: usize = 53;
 
 pub fn part_one(data: &str) -> u32 {
     data.lines().fold(0, |acc, line| {
-        let (first, second) = line.split_at(line.len() / 2);
+        let (first, second) = compartments(line);
         let first = HashSet::<char>::from_iter(first.chars());
         let second = HashSet::<char>::from_iter(second.chars());
         acc + first.intersection(&second).map(char_to_digit).sum::<u32>()
@@ -24,6 +34,436 @@ pub fn part_two(data: &str) -> u32 {
     sum
 }
 
+#[derive(Debug)]
+pub struct Inventory {
+    items: BTreeMap<u32, ItemStats>,
+    elf_priorities: Vec<u32>,
+}
+
+#[derive(Debug)]
+struct ItemStats {
+    item: char,
+    count: usize,
+    carriers: Vec<usize>,
+}
+
+impl Inventory {
+    pub fn new(data: &str) -> Result<Self> {
+        let mut items = BTreeMap::<u32, ItemStats>::new();
+        let mut elf_priorities = Vec::new();
+        for (elf, line) in data.lines().enumerate() {
+            if let Some(item) = line.chars().find(|item| !item.is_ascii_alphabetic()) {
+                return Err(format!("Line {}: '{item}' is not an item!", elf + 1).into());
+            }
+            for item in line.chars() {
+                let stats = items.entry(char_to_digit(&item)).or_insert(ItemStats {
+                    item,
+                    count: 0,
+                    carriers: Vec::new(),
+                });
+                stats.count += 1;
+                if stats.carriers.last() != Some(&elf) {
+                    stats.carriers.push(elf);
+                }
+            }
+            elf_priorities.push(line.chars().map(|item| char_to_digit(&item)).sum());
+        }
+        Ok(Self {
+            items,
+            elf_priorities,
+        })
+    }
+
+    pub fn carriers(&self, item: char) -> &[usize] {
+        self.stats(item)
+            .map(|stats| stats.carriers.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn frequency(&self, item: char) -> usize {
+        self.stats(item).map(|stats| stats.count).unwrap_or(0)
+    }
+
+    pub fn frequencies(&self) -> Vec<(char, usize)> {
+        self.items
+            .values()
+            .map(|stats| (stats.item, stats.count))
+            .collect()
+    }
+
+    pub fn rarest(&self) -> Option<(char, usize)> {
+        self.frequencies().into_iter().reduce(|rarest, current| {
+            if current.1 < rarest.1 {
+                current
+            } else {
+                rarest
+            }
+        })
+    }
+
+    pub fn most_common(&self) -> Option<(char, usize)> {
+        self.frequencies().into_iter().reduce(|common, current| {
+            if current.1 > common.1 {
+                current
+            } else {
+                common
+            }
+        })
+    }
+
+    pub fn priority_sums(&self) -> &[u32] {
+        &self.elf_priorities
+    }
+
+    fn stats(&self, item: char) -> Option<&ItemStats> {
+        item.is_ascii_alphabetic()
+            .then(|| self.items.get(&char_to_digit(&item)))
+            .flatten()
+    }
+}
+
+impl Display for Inventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<4} {:>8} {:>6} {:>8}",
+            "item", "priority", "count", "carriers"
+        )?;
+        for (priority, stats) in &self.items {
+            writeln!(
+                f,
+                "{:<4} {:>8} {:>6} {:>8}",
+                stats.item,
+                priority,
+                stats.count,
+                stats.carriers.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadgeObjective {
+    Maximize,
+    Minimize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Grouping {
+    pub groups: Vec<[usize; 3]>,
+    pub badge_priority: u32,
+    /// Whether the search finished, so no other grouping does better.
+    pub optimal: bool,
+}
+
+// The number of partial groupings `group_elves` looks at before settling for
+// the best grouping found so far. It always carries on until it finds one.
+const GROUPING_SEARCH_LIMIT: usize = 20000;
+
+/// Partitions rucksacks given in any order into groups of three that share
+/// exactly one item type, or returns `None` when no such partition exists.
+/// Groups refer to rucksacks by line index. Large inputs have far too many
+/// partitions to search them all, so the result may not be optimal, and the
+/// search gives up with an error when it runs out before settling whether
+/// any partition exists.
+pub fn group_elves(data: &str, objective: BadgeObjective) -> Result<Option<Grouping>> {
+    let rucksacks: Vec<u64> = data
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            if line.chars().all(|item| item.is_ascii_alphabetic()) {
+                Ok(item_set(line))
+            } else {
+                Err(format!("Line {}: '{line}' is not a rucksack!", index + 1))
+            }
+        })
+        .collect::<std::result::Result<_, _>>()?;
+    if !rucksacks.len().is_multiple_of(3) {
+        return Ok(None);
+    }
+    let mut holders = vec![Vec::new(); ITEM_TYPES];
+    for (index, &items) in rucksacks.iter().enumerate() {
+        for item in items_of(items) {
+            holders[item as usize].push(index);
+        }
+    }
+    let mut search = GroupSearch {
+        rucksacks: &rucksacks,
+        holders,
+        objective,
+        limits: Vec::new(),
+        remaining: vec![true; rucksacks.len()],
+        groups: Vec::new(),
+        best: None,
+        budget: GROUPING_SEARCH_LIMIT,
+        cut_short: false,
+    };
+    // Rucksacks that never share a group with each other have to be
+    // partitioned separately, so each such part needs a multiple of three.
+    let mut parts: Vec<usize> = (0..rucksacks.len()).collect();
+    for index in 0..rucksacks.len() {
+        let candidates = search.candidates(index);
+        let Some(&(limit, _)) = candidates.first() else {
+            return Ok(None);
+        };
+        search.limits.push(limit);
+        for (_, group) in candidates {
+            for other in group {
+                let (a, b) = (find_part(&mut parts, index), find_part(&mut parts, other));
+                parts[a] = b;
+            }
+        }
+    }
+    let mut sizes = vec![0usize; rucksacks.len()];
+    for index in 0..rucksacks.len() {
+        sizes[find_part(&mut parts, index)] += 1;
+    }
+    if sizes.iter().any(|size| !size.is_multiple_of(3)) {
+        return Ok(None);
+    }
+    search.run(0);
+    match search.best {
+        Some((badge_priority, groups)) => Ok(Some(Grouping {
+            groups,
+            badge_priority,
+            optimal: !search.cut_short,
+        })),
+        None if search.cut_short => Err(format!(
+            "Gave up after {GROUPING_SEARCH_LIMIT} partial groupings without finding one!"
+        )
+        .into()),
+        None => Ok(None),
+    }
+}
+
+struct GroupSearch<'a> {
+    rucksacks: &'a [u64],
+    // The rucksacks holding each item type.
+    holders: Vec<Vec<usize>>,
+    objective: BadgeObjective,
+    // The best badge each rucksack gets in any group at all.
+    limits: Vec<u32>,
+    remaining: Vec<bool>,
+    groups: Vec<[usize; 3]>,
+    best: Option<(u32, Vec<[usize; 3]>)>,
+    budget: usize,
+    cut_short: bool,
+}
+
+impl GroupSearch<'_> {
+    fn run(&mut self, total: u32) {
+        if let Some((best, _)) = self.best {
+            if !self.is_better(total + self.bound(), best) {
+                return;
+            }
+        }
+        let Some(first) = self.remaining.iter().position(|&r| r) else {
+            self.best = Some((total, self.groups.clone()));
+            return;
+        };
+        if self.budget == 0 {
+            self.cut_short = true;
+            return;
+        }
+        self.budget -= 1;
+        let stranded = (0..self.remaining.len()).any(|index| {
+            self.remaining[index]
+                && self
+                    .each_group(index, |_, _| ControlFlow::Break(()))
+                    .is_continue()
+        });
+        if stranded {
+            return;
+        }
+        for (badge, group) in self.candidates(first) {
+            group
+                .iter()
+                .for_each(|&index| self.remaining[index] = false);
+            self.groups.push(group);
+            self.run(total + badge);
+            self.groups.pop();
+            group.iter().for_each(|&index| self.remaining[index] = true);
+        }
+    }
+
+    // Every group `first` can form with the remaining rucksacks, best badge
+    // first.
+    fn candidates(&self, first: usize) -> Vec<(u32, [usize; 3])> {
+        let mut candidates = Vec::new();
+        let _ = self.each_group(first, |badge, group| {
+            candidates.push((badge, group));
+            ControlFlow::Continue(())
+        });
+        match self.objective {
+            BadgeObjective::Maximize => candidates.sort_by_key(|&(badge, _)| Reverse(badge)),
+            BadgeObjective::Minimize => candidates.sort_by_key(|&(badge, _)| badge),
+        }
+        candidates
+    }
+
+    fn each_group(
+        &self,
+        first: usize,
+        mut visit: impl FnMut(u32, [usize; 3]) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        for item in items_of(self.rucksacks[first]) {
+            let others: Vec<usize> = self.holders[item as usize]
+                .iter()
+                .copied()
+                .filter(|&index| index != first && self.remaining[index])
+                .collect();
+            for (position, &second) in others.iter().enumerate() {
+                let shared = self.rucksacks[first] & self.rucksacks[second];
+                for &third in &others[position + 1..] {
+                    if shared & self.rucksacks[third] == 1 << item {
+                        visit(item, [first, second, third])?;
+                    }
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    // A group's badge is no better than the limit of any of its rucksacks, so
+    // the remaining rucksacks do best grouped in order of their limits.
+    fn bound(&self) -> u32 {
+        let mut limits: Vec<u32> = (0..self.remaining.len())
+            .filter(|&index| self.remaining[index])
+            .map(|index| self.limits[index])
+            .collect();
+        match self.objective {
+            BadgeObjective::Maximize => limits.sort_unstable_by_key(|&limit| Reverse(limit)),
+            BadgeObjective::Minimize => limits.sort_unstable(),
+        }
+        limits.iter().skip(2).step_by(3).sum()
+    }
+
+    fn is_better(&self, priority: u32, best: u32) -> bool {
+        match self.objective {
+            BadgeObjective::Maximize => priority > best,
+            BadgeObjective::Minimize => priority < best,
+        }
+    }
+}
+
+fn find_part(parts: &mut [usize], index: usize) -> usize {
+    if parts[index] != index {
+        parts[index] = find_part(parts, parts[index]);
+    }
+    parts[index]
+}
+
+fn items_of(items: u64) -> impl Iterator<Item = u32> {
+    (1..ITEM_TYPES as u32).filter(move |&item| items & 1 << item != 0)
+}
+
+fn item_set(line: &str) -> u64 {
+    line.chars()
+        .fold(0, |set, item| set | 1 << char_to_digit(&item))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rebalance {
+    pub swaps: usize,
+    pub line: String,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RebalanceSummary {
+    pub total_swaps: usize,
+    pub unfixable: Vec<usize>,
+}
+
+pub fn rebalance_all(data: &str) -> RebalanceSummary {
+    data.lines()
+        .enumerate()
+        .fold(RebalanceSummary::default(), |mut summary, (index, line)| {
+            match rebalance(line) {
+                Some(rebalanced) => summary.total_swaps += rebalanced.swaps,
+                None => summary.unfixable.push(index),
+            }
+            summary
+        })
+}
+
+/// Finds the fewest swaps between compartments so that no item type is in
+/// both, returning `None` when the item counts make that impossible or the
+/// line holds something other than items.
+pub fn rebalance(line: &str) -> Option<Rebalance> {
+    if !line.chars().all(|item| item.is_ascii_alphabetic()) {
+        return None;
+    }
+    let (first, second) = compartments(line);
+    let mut first: Vec<char> = first.chars().collect();
+    let mut second: Vec<char> = second.chars().collect();
+    let first_counts = count_items(&first);
+    let second_counts = count_items(&second);
+    let in_first = choose_first_compartment(&first_counts, &second_counts, first.len())?;
+    let outgoing: Vec<usize> = (0..first.len())
+        .filter(|&i| !in_first[char_to_digit(&first[i]) as usize])
+        .collect();
+    let incoming: Vec<usize> = (0..second.len())
+        .filter(|&i| in_first[char_to_digit(&second[i]) as usize])
+        .collect();
+    for (&out, &inc) in outgoing.iter().zip(&incoming) {
+        std::mem::swap(&mut first[out], &mut second[inc]);
+    }
+    Some(Rebalance {
+        swaps: outgoing.len(),
+        line: first.into_iter().chain(second).collect(),
+    })
+}
+
+// Picks the item types that end up in the first compartment. The chosen
+// types must fill it exactly, and the cost of a type is how many of its
+// items currently sit in the second compartment.
+fn choose_first_compartment(
+    first_counts: &[usize; ITEM_TYPES],
+    second_counts: &[usize; ITEM_TYPES],
+    capacity: usize,
+) -> Option<[bool; ITEM_TYPES]> {
+    let mut best: Vec<Vec<Option<usize>>> = vec![vec![None; capacity + 1]; ITEM_TYPES + 1];
+    best[0][0] = Some(0);
+    for item in 0..ITEM_TYPES {
+        let total = first_counts[item] + second_counts[item];
+        for filled in 0..=capacity {
+            let skip = best[item][filled];
+            let take = filled
+                .checked_sub(total)
+                .and_then(|rest| best[item][rest])
+                .map(|cost| cost + second_counts[item]);
+            best[item + 1][filled] = match (skip, take) {
+                (Some(skip), Some(take)) => Some(usize::min(skip, take)),
+                (skip, take) => skip.or(take),
+            };
+        }
+    }
+    best[ITEM_TYPES][capacity]?;
+    let mut chosen = [false; ITEM_TYPES];
+    let mut filled = capacity;
+    for item in (0..ITEM_TYPES).rev() {
+        if best[item + 1][filled] == best[item][filled] {
+            continue;
+        }
+        chosen[item] = true;
+        filled -= first_counts[item] + second_counts[item];
+    }
+    Some(chosen)
+}
+
+fn count_items(items: &[char]) -> [usize; ITEM_TYPES] {
+    let mut counts = [0; ITEM_TYPES];
+    for item in items {
+        counts[char_to_digit(item) as usize] += 1;
+    }
+    counts
+}
+
+fn compartments(line: &str) -> (&str, &str) {
+    line.split_at(line.len() / 2)
+}
+
 fn char_to_digit(c: &char) -> u32 {
     if c.is_lowercase() {
         *c as u32 - 96
@@ -34,7 +474,11 @@ fn char_to_digit(c: &char) -> u32 {
 
 #[cfg(test)]
 mod test {
-    use super::{part_one, part_two};
+    use super::{
+        compartments, group_elves, item_set, part_one, part_two, rebalance, rebalance_all,
+        BadgeObjective, Inventory,
+    };
+    use std::collections::HashSet;
 
     #[test]
     fn test_part_one() {
@@ -49,4 +493,163 @@ mod test {
         let result = part_two(data);
         assert_eq!(result, 2631)
     }
+
+    #[test]
+    fn rebalance_should_separate_item_types_with_fewest_swaps() {
+        let result = rebalance("vJrwpWtwJgWrhcsFMMfFFhFp").unwrap();
+        assert_eq!(result.swaps, 1);
+        let (first, second) = compartments(&result.line);
+        let first = HashSet::<char>::from_iter(first.chars());
+        let second = HashSet::<char>::from_iter(second.chars());
+        assert!(first.is_disjoint(&second));
+    }
+
+    #[test]
+    fn rebalance_all_should_report_unfixable_rucksacks() {
+        let data = r#"
+vJrwpWtwJgWrhcsFMMfFFhFp
+aaaabb
+abcdef"#
+            .trim();
+        let result = rebalance_all(data);
+        assert_eq!(result.total_swaps, 1);
+        assert_eq!(result.unfixable, vec![1]);
+        assert_eq!(rebalance("ab cd"), None);
+        assert_eq!(rebalance("abéd"), None);
+    }
+
+    #[test]
+    fn group_elves_should_find_groups_sharing_one_badge() {
+        let data = r#"
+ttgJtRGJQctTZtZT
+vJrwpWtwJgWrhcsFMMfFFhFp
+CrZsJsPPZsGzwwsLwLmpwMDw
+jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL
+wMqvLMZHhHMvwLHjbvcjnnSBnvTQFn
+PmmdzqPrVvPwwTWBwg"#
+            .trim();
+        let lines: Vec<&str> = data.lines().collect();
+        let max = group_elves(data, BadgeObjective::Maximize)
+            .unwrap()
+            .unwrap();
+        let min = group_elves(data, BadgeObjective::Minimize)
+            .unwrap()
+            .unwrap();
+        assert_eq!(max.badge_priority, 70);
+        assert_eq!(min.badge_priority, 53);
+        assert!(max.optimal && min.optimal);
+        for group in max.groups.iter().chain(&min.groups) {
+            let badge = group
+                .iter()
+                .fold(u64::MAX, |acc, &index| acc & item_set(lines[index]));
+            assert_eq!(badge.count_ones(), 1);
+        }
+    }
+
+    #[test]
+    fn group_elves_should_handle_shuffled_rucksacks() {
+        let data = r#"
+GiRddbGUdaenBkGTsdMcae
+GUOZAMxhTichKnMdoG
+RIeqtDgAbmYDbvsUUogXCV
+WLGlymoxQMmeHBGCxm
+VHEudvhejXExYLYtPKJJBxYctzzHMaWLcJbKvbrALweYrKMg
+jhPqFnCZStXnNQaqZlnZTvfKXjVJ
+RmYTJVcTrVDTZFHZYpAGuropxZZB
+OraJLnTHxAjzDTIPxPukPamWHNVU
+GpDsSFyNNjqoNijv
+uPVYpgneGevrUInEsJdtJqZU
+gwJjJuNEWwjykqtGsUufVN
+OpbNmtgfTdwHfJRkPJBUkJpWqvoLXG
+ystrMzmusKMenowLRk
+dnHiQxyzUyJHykuHKQUhJVsI
+XVjFoVrFRHzzygrvOxonaeDAdwOXLH
+PJKPbfraZszWWWorDIqa
+ImoOlgQUhpmToUco
+zRXsLQIQrSrmTWHcGHKs
+ODUsGWRzCzLcIAxRDG
+TXkvhwjBSXLZHbuHCRGK
+oObtlfKvdnGZAllWuh
+PHLUwITMYjePazlyXEVjKWxejJQXYKCQ
+xQdkNOJjWaLLzlsnJg
+exPiHtOckLFahbLjKN
+jXhSOAyPvALLhqtolgMeqarTrQTGHqvlwHthLtvSqVpFBF
+DwQdUTdcYwpoYRBNyWHhVpmmoffUuFZqUdqQsWlVmyhB
+UHalWSWPJsVDvQMMlG
+TuXXHrmBIFRUHYQBhENFciQSuq
+BcsfUYTUeTLCLvUy
+hxrpFfrVdwJkWwordCTsOsWrGqyHAhQObYWTcoGXEsJvde"#
+            .trim();
+        let lines: Vec<&str> = data.lines().collect();
+        for objective in [BadgeObjective::Maximize, BadgeObjective::Minimize] {
+            let grouping = group_elves(data, objective).unwrap().unwrap();
+            let mut used: Vec<usize> = grouping.groups.iter().flatten().copied().collect();
+            used.sort_unstable();
+            assert_eq!(used, (0..lines.len()).collect::<Vec<usize>>());
+            let total: u32 = grouping
+                .groups
+                .iter()
+                .map(|group| {
+                    let badge = group
+                        .iter()
+                        .fold(u64::MAX, |acc, &index| acc & item_set(lines[index]));
+                    assert_eq!(badge.count_ones(), 1);
+                    badge.trailing_zeros()
+                })
+                .sum();
+            assert_eq!(total, grouping.badge_priority);
+        }
+    }
+
+    #[test]
+    fn group_elves_should_return_none_without_valid_partition() {
+        let data = r#"
+abx
+aby
+abz"#
+            .trim();
+        assert!(group_elves(data, BadgeObjective::Maximize)
+            .unwrap()
+            .is_none());
+        // Every rucksack has a group on its own, but no partition uses them all.
+        let data: String = ('c'..='z')
+            .chain('A'..='B')
+            .map(|item| format!("a{item}\n"))
+            .chain(('C'..='F').map(|item| format!("b{item}\n")))
+            .collect();
+        assert!(group_elves(data.trim(), BadgeObjective::Maximize)
+            .unwrap()
+            .is_none());
+        assert!(group_elves("ab\nab\na b", BadgeObjective::Maximize).is_err());
+    }
+
+    #[test]
+    fn inventory_should_answer_item_queries() {
+        let data = r#"
+vJrwpWtwJgWrhcsFMMfFFhFp
+jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL
+PmmdzqPrVvPwwTWBwg"#
+            .trim();
+        let inventory = Inventory::new(data).unwrap();
+        assert_eq!(inventory.carriers('P'), &[2]);
+        assert_eq!(inventory.carriers('r'), &[0, 1, 2]);
+        assert_eq!(inventory.carriers('x'), &[] as &[usize]);
+        assert_eq!(inventory.frequency('F'), 7);
+        assert_eq!(inventory.most_common(), Some(('F', 7)));
+        assert_eq!(inventory.rarest(), Some(('c', 1)));
+    }
+
+    #[test]
+    fn inventory_should_render_table() {
+        let inventory = Inventory::new("aab\nB").unwrap();
+        let table = inventory.to_string();
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[1], "a           1      2        1");
+        assert_eq!(rows[3], "B          28      1        1");
+        assert_eq!(inventory.priority_sums(), &[4, 28]);
+        let error = Inventory::new("k1").unwrap_err();
+        assert_eq!(error.to_string(), "Line 1: '1' is not an item!");
+        assert!(Inventory::new("a\na b").is_err());
+    }
 }
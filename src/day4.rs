@@ -1,51 +1,651 @@
-pub fn part_one(data: &str) -> usize {
-    data.lines()
-        .map(parse_to_vec)
-        .filter(|nums: &Vec<usize>| is_fully_overlapping(nums))
-        .count()
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    error::Error,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+pub fn part_one(data: &str) -> Result<usize> {
+    Ok(parse_assignments(data)?
+        .iter()
+        .filter(|assignment| assignment.is_fully_overlapping())
+        .count())
+}
+
+pub fn part_two(data: &str) -> Result<usize> {
+    Ok(parse_assignments(data)?
+        .iter()
+        .filter(|assignment| assignment.is_overlapping())
+        .count())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SectionRange {
+    start: usize,
+    end: usize,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl SectionRange {
+    pub fn new(start: usize, end: usize) -> Result<Self> {
+        if start > end {
+            return Err(format!("Section range {start}-{end} is reversed!").into());
+        }
+        if start == 0 {
+            return Err(format!("Section range {start}-{end} starts before section 1!").into());
+        }
+        Ok(Self { start, end })
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn contains(&self, other: &SectionRange) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    pub fn contains_section(&self, section: usize) -> bool {
+        (self.start..=self.end).contains(&section)
+    }
+
+    pub fn overlaps(&self, other: &SectionRange) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    pub fn intersection(&self, other: &SectionRange) -> Option<SectionRange> {
+        self.overlaps(other).then(|| SectionRange {
+            start: usize::max(self.start, other.start),
+            end: usize::min(self.end, other.end),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start + 1
+    }
+}
+
+impl Display for SectionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
 }
 
-pub fn part_two(data: &str) -> usize {
+impl FromStr for SectionRange {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Section range '{s}' is missing a '-'!"))?;
+        let parse = |num: &str| {
+            num.trim()
+                .parse::<usize>()
+                .map_err(|e| format!("Bad section number '{num}': {e}"))
+        };
+        SectionRange::new(parse(start)?, parse(end)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Assignment {
+    pub first: SectionRange,
+    pub second: SectionRange,
+}
+
+impl Assignment {
+    pub fn is_fully_overlapping(&self) -> bool {
+        self.first.contains(&self.second) || self.second.contains(&self.first)
+    }
+
+    pub fn is_overlapping(&self) -> bool {
+        self.first.overlaps(&self.second)
+    }
+
+    pub fn overlap(&self) -> Overlap {
+        let size = self
+            .first
+            .intersection(&self.second)
+            .map_or(0, |intersection| intersection.len());
+        Overlap {
+            size,
+            first_redundant: size as f64 / self.first.len() as f64,
+            second_redundant: size as f64 / self.second.len() as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Overlap {
+    pub size: usize,
+    pub first_redundant: f64,
+    pub second_redundant: f64,
+}
+
+impl Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.first, self.second)
+    }
+}
+
+impl FromStr for Assignment {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (first, second) = s
+            .split_once(',')
+            .ok_or_else(|| format!("Assignment '{s}' is missing a ','!"))?;
+        Ok(Self {
+            first: first.parse()?,
+            second: second.parse()?,
+        })
+    }
+}
+
+pub fn parse_assignments(data: &str) -> Result<Vec<Assignment>> {
     data.lines()
-        .map(parse_to_vec)
-        .filter(|nums: &Vec<usize>| is_overlapping(nums))
-        .count()
+        .enumerate()
+        .map(|(index, line)| {
+            line.parse()
+                .map_err(|e| format!("Line {}: {e}", index + 1).into())
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReassignMode {
+    KeepLength,
+    Shrink,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Reassignment {
+    pub assignments: Vec<Assignment>,
+    pub total_change: usize,
+}
+
+impl Display for Reassignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.assignments
+            .iter()
+            .try_for_each(|assignment| writeln!(f, "{assignment}"))
+    }
+}
+
+/// Moves the ranges of every overlapping pair so they no longer overlap while
+/// still covering the sections the pair covered, changing the range ends as
+/// little as possible. `KeepLength` may grow the covered sections to keep
+/// each elf's range length, `Shrink` covers exactly the same sections.
+pub fn reassign(assignments: &[Assignment], mode: ReassignMode) -> Result<Reassignment> {
+    let mut reassignment = Reassignment {
+        assignments: Vec::with_capacity(assignments.len()),
+        total_change: 0,
+    };
+    for (index, assignment) in assignments.iter().enumerate() {
+        let (change, planned) = if assignment.is_overlapping() {
+            plan_pair(assignment, mode)
+                .ok_or_else(|| format!("Line {}: {assignment} cannot be split!", index + 1))?
+        } else {
+            (0, *assignment)
+        };
+        reassignment.total_change += change;
+        reassignment.assignments.push(planned);
+    }
+    Ok(reassignment)
+}
+
+fn plan_pair(assignment: &Assignment, mode: ReassignMode) -> Option<(usize, Assignment)> {
+    let Assignment { first, second } = *assignment;
+    let start = usize::min(first.start, second.start);
+    let end = usize::max(first.end, second.end);
+    let span = |from: usize, len: usize| SectionRange {
+        start: from,
+        end: from + len - 1,
+    };
+    let mut candidates = Vec::new();
+    match mode {
+        ReassignMode::KeepLength => {
+            let lowest = (end + 1).saturating_sub(first.len() + second.len()).max(1);
+            for from in lowest..=start {
+                candidates.push((
+                    span(from, first.len()),
+                    span(from + first.len(), second.len()),
+                ));
+                candidates.push((
+                    span(from + second.len(), first.len()),
+                    span(from, second.len()),
+                ));
+            }
+        }
+        ReassignMode::Shrink => {
+            for split in start + 1..=end {
+                candidates.push((span(start, split - start), span(split, end + 1 - split)));
+                candidates.push((span(split, end + 1 - split), span(start, split - start)));
+            }
+        }
+    }
+    candidates
+        .into_iter()
+        .map(|(new_first, new_second)| {
+            let change = range_change(&first, &new_first) + range_change(&second, &new_second);
+            (
+                change,
+                Assignment {
+                    first: new_first,
+                    second: new_second,
+                },
+            )
+        })
+        .min_by_key(|(change, _)| *change)
+}
+
+fn range_change(old: &SectionRange, new: &SectionRange) -> usize {
+    old.start.abs_diff(new.start) + old.end.abs_diff(new.end)
+}
+
+/// Draws each pair the way the puzzle does, e.g. `.234.....  2-4`, using at
+/// most `width` columns. When there are more sections than columns each
+/// column stands for several sections and covered columns are drawn as `#`.
+pub fn render(assignments: &[Assignment], width: usize) -> String {
+    let last = assignments
+        .iter()
+        .map(|assignment| usize::max(assignment.first.end, assignment.second.end))
+        .max()
+        .unwrap_or(0);
+    let width = width.max(1);
+    let scale = last.div_ceil(width).max(1);
+    let columns = last.div_ceil(scale);
+    let row = |range: &SectionRange| {
+        let drawing: String = (0..columns)
+            .map(|column| {
+                let sections = SectionRange {
+                    start: column * scale + 1,
+                    end: (column + 1) * scale,
+                };
+                match (range.overlaps(&sections), scale) {
+                    (false, _) => '.',
+                    (true, 1) => char::from_digit((sections.start % 10) as u32, 10).unwrap(),
+                    (true, _) => '#',
+                }
+            })
+            .collect();
+        format!("{drawing}  {}-{}\n", range.start, range.end)
+    };
+    assignments
+        .iter()
+        .map(|assignment| row(&assignment.first) + &row(&assignment.second))
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
-fn parse_to_vec(line: &str) -> Vec<usize> {
-    line.split(',')
-        .flat_map(|line| line.split('-').collect::<Vec<&str>>())
-        .map(|s| s.parse().unwrap())
+/// One elf of a pair, identified by the line the pair is on, counted from one
+/// like in parse errors, and its place in the pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Elf {
+    pub line: usize,
+    pub index: usize,
+}
+
+pub fn elves(assignments: &[Assignment]) -> Vec<(Elf, SectionRange)> {
+    assignments
+        .iter()
+        .enumerate()
+        .flat_map(|(index, assignment)| {
+            let line = index + 1;
+            [
+                (Elf { line, index: 0 }, assignment.first),
+                (Elf { line, index: 1 }, assignment.second),
+            ]
+        })
         .collect()
 }
 
-fn is_fully_overlapping(nums: &[usize]) -> bool {
-    (nums[0]..=nums[1]).contains(&nums[2]) && (nums[0]..=nums[1]).contains(&nums[3])
-        || (nums[2]..=nums[3]).contains(&nums[0]) && (nums[2]..=nums[3]).contains(&nums[1])
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub uncovered: Vec<SectionRange>,
+    pub single: Vec<SectionRange>,
+    pub multiple: Vec<SectionRange>,
+    pub max_overlap: usize,
+}
+
+/// Classifies every section between the lowest and highest assigned section
+/// by how many elves cover it.
+pub fn coverage(assignments: &[Assignment]) -> Coverage {
+    let mut events: Vec<(usize, isize)> = elves(assignments)
+        .iter()
+        .flat_map(|(_, range)| [(range.start, 1), (range.end + 1, -1)])
+        .collect();
+    events.sort_unstable();
+    let mut coverage = Coverage::default();
+    let mut depth = 0;
+    let mut events = events.iter().peekable();
+    while let Some(&(section, change)) = events.next() {
+        depth += change;
+        let Some(&&(next, _)) = events.peek() else {
+            break;
+        };
+        if next == section {
+            continue;
+        }
+        coverage.max_overlap = usize::max(coverage.max_overlap, depth as usize);
+        let ranges = match depth {
+            0 => &mut coverage.uncovered,
+            1 => &mut coverage.single,
+            _ => &mut coverage.multiple,
+        };
+        match ranges.last_mut() {
+            Some(last) if last.end + 1 == section => last.end = next - 1,
+            _ => ranges.push(SectionRange {
+                start: section,
+                end: next - 1,
+            }),
+        }
+    }
+    coverage
+}
+
+/// Finds every pair of elves, on any line, whose ranges overlap using a
+/// sweep over range starts.
+pub fn overlapping_pairs(assignments: &[Assignment]) -> Vec<(Elf, Elf)> {
+    let mut elves = elves(assignments);
+    elves.sort_unstable_by_key(|(elf, range)| (range.start, *elf));
+    let mut active = BTreeSet::<(usize, Elf)>::new();
+    let mut pairs = Vec::new();
+    for (elf, range) in elves {
+        while let Some(&(end, other)) = active.first() {
+            if end >= range.start {
+                break;
+            }
+            active.remove(&(end, other));
+        }
+        pairs.extend(active.iter().map(|&(_, other)| (other, elf)));
+        active.insert((range.end, elf));
+    }
+    pairs
+}
+
+/// Centered interval tree answering which elves cover a section or touch a
+/// range of sections.
+pub struct SectionIndex {
+    root: Option<Box<IndexNode>>,
 }
 
-fn is_overlapping(nums: &[usize]) -> bool {
-    (nums[0]..=nums[1]).contains(&nums[2])
-        || (nums[0]..=nums[1]).contains(&nums[3])
-        || (nums[2]..=nums[3]).contains(&nums[0])
-        || (nums[2]..=nums[3]).contains(&nums[1])
+struct IndexNode {
+    center: usize,
+    by_start: Vec<(Elf, SectionRange)>,
+    by_end: Vec<(Elf, SectionRange)>,
+    left: Option<Box<IndexNode>>,
+    right: Option<Box<IndexNode>>,
+}
+
+impl SectionIndex {
+    pub fn new(assignments: &[Assignment]) -> Self {
+        Self {
+            root: IndexNode::build(elves(assignments)),
+        }
+    }
+
+    pub fn at(&self, section: usize) -> Vec<Elf> {
+        self.overlapping(&SectionRange {
+            start: section,
+            end: section,
+        })
+    }
+
+    pub fn overlapping(&self, range: &SectionRange) -> Vec<Elf> {
+        let mut found = Vec::new();
+        let mut nodes: Vec<&IndexNode> = self.root.iter().map(|node| node.as_ref()).collect();
+        while let Some(node) = nodes.pop() {
+            if range.end < node.center {
+                found.extend(
+                    node.by_start
+                        .iter()
+                        .take_while(|(_, elf_range)| elf_range.start <= range.end)
+                        .map(|(elf, _)| *elf),
+                );
+                nodes.extend(node.left.as_deref());
+            } else if range.start > node.center {
+                found.extend(
+                    node.by_end
+                        .iter()
+                        .take_while(|(_, elf_range)| elf_range.end >= range.start)
+                        .map(|(elf, _)| *elf),
+                );
+                nodes.extend(node.right.as_deref());
+            } else {
+                found.extend(node.by_start.iter().map(|(elf, _)| *elf));
+                nodes.extend(node.left.as_deref());
+                nodes.extend(node.right.as_deref());
+            }
+        }
+        found
+    }
+}
+
+impl IndexNode {
+    fn build(elves: Vec<(Elf, SectionRange)>) -> Option<Box<IndexNode>> {
+        if elves.is_empty() {
+            return None;
+        }
+        let mut ends: Vec<usize> = elves
+            .iter()
+            .flat_map(|(_, range)| [range.start, range.end])
+            .collect();
+        ends.sort_unstable();
+        let center = ends[ends.len() / 2];
+        let (mut left, mut right, mut by_start) = (Vec::new(), Vec::new(), Vec::new());
+        for elf in elves {
+            if elf.1.end < center {
+                left.push(elf);
+            } else if elf.1.start > center {
+                right.push(elf);
+            } else {
+                by_start.push(elf);
+            }
+        }
+        let mut by_end = by_start.clone();
+        by_start.sort_unstable_by_key(|(_, range)| range.start);
+        by_end.sort_unstable_by_key(|(_, range)| Reverse(range.end));
+        Some(Box::new(IndexNode {
+            center,
+            by_start,
+            by_end,
+            left: IndexNode::build(left),
+            right: IndexNode::build(right),
+        }))
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{part_one, part_two};
+    use super::{
+        coverage, elves, overlapping_pairs, parse_assignments, part_one, part_two, reassign,
+        render, ReassignMode, SectionIndex, SectionRange,
+    };
 
     #[test]
     fn test_part_one() {
         let data = include_str!("../resources/day3part1").trim();
-        let result = part_one(data);
+        let result = part_one(data).unwrap();
         assert_eq!(result, 547);
     }
 
     #[test]
     fn test_part_two() {
         let data = include_str!("../resources/day3part1").trim();
-        let result = part_two(data);
+        let result = part_two(data).unwrap();
         assert_eq!(result, 843);
     }
+
+    #[test]
+    fn section_range_should_compare_ranges() {
+        let range: SectionRange = "2-8".parse().unwrap();
+        let other: SectionRange = "6-10".parse().unwrap();
+        assert!(range.contains(&"3-7".parse().unwrap()));
+        assert!(range.overlaps(&other));
+        assert_eq!(range.intersection(&other), Some("6-8".parse().unwrap()));
+        assert_eq!(range.intersection(&"9-9".parse().unwrap()), None);
+        assert_eq!(range.len(), 7);
+    }
+
+    #[test]
+    fn parse_assignments_should_report_bad_lines() {
+        let reversed = parse_assignments("2-4,6-8\n8-2,1-3").unwrap_err();
+        assert_eq!(
+            reversed.to_string(),
+            "Line 2: Section range 8-2 is reversed!"
+        );
+        let short = parse_assignments("2-4").unwrap_err();
+        assert_eq!(
+            short.to_string(),
+            "Line 1: Assignment '2-4' is missing a ','!"
+        );
+        let zero = parse_assignments("0-2,1-3").unwrap_err();
+        assert_eq!(
+            zero.to_string(),
+            "Line 1: Section range 0-2 starts before section 1!"
+        );
+    }
+
+    #[test]
+    fn coverage_should_classify_sections() {
+        let assignments = parse_assignments("1-2,5-6\n2-3,2-2").unwrap();
+        let result = coverage(&assignments);
+        let range = |s: &str| s.parse::<SectionRange>().unwrap();
+        assert_eq!(result.uncovered, vec![range("4-4")]);
+        assert_eq!(
+            result.single,
+            vec![range("1-1"), range("3-3"), range("5-6")]
+        );
+        assert_eq!(result.multiple, vec![range("2-2")]);
+        assert_eq!(result.max_overlap, 3);
+    }
+
+    #[test]
+    fn overlapping_pairs_should_match_pairwise_comparison() {
+        let data = r#"
+2-4,6-8
+2-3,4-5
+5-7,7-9
+2-8,3-7
+6-6,4-6
+2-6,4-8"#
+            .trim();
+        let assignments = parse_assignments(data).unwrap();
+        let elves = elves(&assignments);
+        let mut expected = Vec::new();
+        for (i, (elf, range)) in elves.iter().enumerate() {
+            for (other, other_range) in &elves[i + 1..] {
+                if range.overlaps(other_range) {
+                    expected.push((*elf.min(other), *elf.max(other)));
+                }
+            }
+        }
+        let mut result: Vec<_> = overlapping_pairs(&assignments)
+            .into_iter()
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        expected.sort_unstable();
+        result.sort_unstable();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn overlap_should_measure_redundancy() {
+        let assignments = parse_assignments("2-8,3-7\n5-7,7-9\n2-4,6-8").unwrap();
+        let overlaps: Vec<_> = assignments.iter().map(|a| a.overlap()).collect();
+        assert_eq!(overlaps[0].size, 5);
+        assert_eq!(overlaps[0].second_redundant, 1.0);
+        assert_eq!(overlaps[1].size, 1);
+        assert_eq!(overlaps[1].first_redundant, 1.0 / 3.0);
+        assert_eq!(overlaps[2].size, 0);
+    }
+
+    #[test]
+    fn render_should_draw_like_the_puzzle() {
+        let assignments = parse_assignments("2-4,6-8\n5-7,7-9").unwrap();
+        let expected = r#"
+.234.....  2-4
+.....678.  6-8
+
+....567..  5-7
+......789  7-9
+"#;
+        assert_eq!(render(&assignments, 10), expected.trim_start());
+    }
+
+    #[test]
+    fn render_should_scale_large_sections() {
+        let assignments = parse_assignments("1-100,51-100").unwrap();
+        assert_eq!(
+            render(&assignments, 10),
+            "##########  1-100\n.....#####  51-100\n"
+        );
+    }
+
+    #[test]
+    fn reassign_should_split_overlapping_pairs() {
+        let assignments = parse_assignments("2-4,6-8\n5-7,7-9\n2-8,3-7").unwrap();
+        let result = reassign(&assignments, ReassignMode::Shrink).unwrap();
+        assert_eq!(result.to_string(), "2-4,6-8\n5-6,7-9\n2-2,3-8\n");
+        assert_eq!(result.total_change, 8);
+        let error = reassign(&parse_assignments("6-6,6-6").unwrap(), ReassignMode::Shrink);
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "Line 1: 6-6,6-6 cannot be split!"
+        );
+    }
+
+    #[test]
+    fn reassign_should_keep_lengths() {
+        let assignments = parse_assignments("5-7,7-9\n6-6,4-6").unwrap();
+        let result = reassign(&assignments, ReassignMode::KeepLength).unwrap();
+        for (old, new) in assignments.iter().zip(&result.assignments) {
+            assert!(!new.is_overlapping());
+            assert_eq!(old.first.len(), new.first.len());
+            assert_eq!(old.second.len(), new.second.len());
+        }
+        assert_eq!(result.to_string(), "4-6,7-9\n6-6,3-5\n");
+        assert_eq!(result.total_change, 4);
+    }
+
+    #[test]
+    fn section_index_should_match_linear_scan() {
+        let data = r#"
+2-4,6-8
+2-3,4-5
+5-7,7-9
+2-8,3-7
+6-6,4-6
+2-6,4-8"#
+            .trim();
+        let assignments = parse_assignments(data).unwrap();
+        let index = SectionIndex::new(&assignments);
+        let elves = elves(&assignments);
+        for start in 1..=10 {
+            for end in start..=10 {
+                let range = SectionRange::new(start, end).unwrap();
+                let mut expected: Vec<_> = elves
+                    .iter()
+                    .filter(|(_, elf_range)| elf_range.overlaps(&range))
+                    .map(|(elf, _)| *elf)
+                    .collect();
+                let mut result = index.overlapping(&range);
+                expected.sort_unstable();
+                result.sort_unstable();
+                assert_eq!(result, expected);
+            }
+        }
+        let mut at_six = index.at(6);
+        at_six.sort_unstable();
+        assert_eq!(at_six.len(), 8);
+        assert_eq!(at_six[0].line, 1);
+    }
 }
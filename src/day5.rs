@@ -1,31 +1,74 @@
-use std::{error::Error, str::Lines};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    cmp::Reverse,
+    collections::{hash_map::DefaultHasher, BinaryHeap, HashMap, VecDeque},
+    error::Error,
+    fmt::{self, Display},
+    hash::{Hash, Hasher},
+    str::Lines,
+};
 
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
 pub fn execute(data: &str, crane: impl Crane) -> Result<Vec<char>> {
-    let mut lines = data.lines();
-    let mut cranes = parse_cranes(&mut lines, 9);
-    parse_instructions(&mut lines)
+    let (mut cranes, instructions) = parse(data)?;
+    instructions
         .iter()
-        .try_for_each(|instruction| crane.mv(&mut cranes, instruction))?;
+        .try_for_each(|instruction| instruction.apply(&crane, &mut cranes))?;
     Ok(cranes
         .into_iter()
         .filter_map(|mut crane| crane.pop())
         .collect())
 }
 
+pub fn execute_with(data: &str, crane: &str) -> Result<Vec<char>> {
+    execute(data, crane_by_name(crane)?)
+}
+
+/// Looks up a crane model by name: `v1`, `v2`, `batch:K`, `capped:N` or
+/// `metered:NAME` wrapping another model.
+pub fn crane_by_name(name: &str) -> Result<Box<dyn Crane>> {
+    let limit = |value: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|e| format!("Bad limit '{value}' for crane '{name}': {e}"))
+    };
+    Ok(match name.split_once(':') {
+        None if name == "v1" => Box::new(CraneV1),
+        None if name == "v2" => Box::new(CraneV2),
+        Some(("batch", value)) => Box::new(BatchCrane(limit(value)?)),
+        Some(("capped", value)) => Box::new(CappedCrane(limit(value)?)),
+        Some(("metered", inner)) => Box::new(MeteredCrane::new(crane_by_name(inner)?)),
+        _ => return Err(format!("Unknown crane '{name}'!").into()),
+    })
+}
+
 pub trait Crane {
     fn get(&self, start: usize, from_crane: &mut Vec<char>) -> Vec<char>;
 
+    fn lifts(&self, _amount: usize) -> Result<usize> {
+        Ok(1)
+    }
+
     fn mv(&self, cranes: &mut [Vec<char>], instruction: &[usize]) -> Result<()> {
-        let &[amount, from, to]  = &instruction[..=2] else {
-            return Err("Instruction was in a bad format!".into())
+        let &[amount, from, to, ..] = instruction else {
+            return Err("Instruction was in a bad format!".into());
         };
+        self.lifts(amount)?;
+        if to >= cranes.len() {
+            return Err("Error getting crane receiving boxes!".into());
+        }
         let items: Vec<char> = {
             let from_crane = cranes
                 .get_mut(from)
                 .ok_or("Error getting crane sending boxes!")?;
-            let start = from_crane.len() - amount;
+            let start = from_crane.len().checked_sub(amount).ok_or_else(|| {
+                format!(
+                    "Cannot move {amount} crates from a stack of {}!",
+                    from_crane.len()
+                )
+            })?;
             self.get(start, from_crane)
         };
         let to_crane = cranes
@@ -36,15 +79,440 @@ pub trait Crane {
     }
 }
 
-struct CraneV1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Flag,
+    Serialize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub tick: usize,
+    pub first_line: usize,
+    pub second_line: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParallelRun {
+    pub makespan: usize,
+    pub tops: Vec<char>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Runs the procedure with several cranes, handing instructions out round
+/// robin. Every tick each crane performs its next move, in crane order. Two
+/// moves touching the same stack in one tick are a conflict: `Flag` records
+/// it and carries on, `Serialize` records it and makes the later crane wait.
+/// `Serialize` also holds a move back while an earlier move on one of its
+/// stacks is still waiting, so the stacks end up as with `execute`.
+pub fn execute_parallel(
+    data: &str,
+    cranes: &[&dyn Crane],
+    policy: ConflictPolicy,
+) -> Result<ParallelRun> {
+    if cranes.is_empty() {
+        return Err("At least one crane is needed!".into());
+    }
+    let (mut stacks, instructions) = parse(data)?;
+    let mut queues = vec![VecDeque::new(); cranes.len()];
+    for (index, instruction) in instructions.into_iter().enumerate() {
+        queues[index % cranes.len()].push_back(instruction);
+    }
+    let mut conflicts = Vec::new();
+    let mut makespan = 0;
+    while queues.iter().any(|queue| !queue.is_empty()) {
+        makespan += 1;
+        let mut claimed: HashMap<usize, usize> = HashMap::new();
+        let mut waiting: HashMap<usize, usize> = HashMap::new();
+        if policy == ConflictPolicy::Serialize {
+            for instruction in queues.iter().flatten() {
+                for stack in [instruction.from, instruction.to] {
+                    let line = waiting.entry(stack).or_insert(instruction.line);
+                    *line = instruction.line.min(*line);
+                }
+            }
+        }
+        for (crane, queue) in cranes.iter().zip(queues.iter_mut()) {
+            let Some(instruction) = queue.front() else {
+                continue;
+            };
+            let earlier = |stack| {
+                let line = *waiting.get(stack)?;
+                (line < instruction.line).then_some(line)
+            };
+            let clash = [instruction.from, instruction.to]
+                .iter()
+                .find_map(|stack| claimed.get(stack).copied().or_else(|| earlier(stack)));
+            if let Some(first_line) = clash {
+                conflicts.push(Conflict {
+                    tick: makespan,
+                    first_line,
+                    second_line: instruction.line,
+                });
+                if policy == ConflictPolicy::Serialize {
+                    continue;
+                }
+            }
+            instruction.apply(crane, &mut stacks)?;
+            claimed.insert(instruction.from, instruction.line);
+            claimed.insert(instruction.to, instruction.line);
+            queue.pop_front();
+        }
+    }
+    Ok(ParallelRun {
+        makespan,
+        tops: stacks
+            .iter()
+            .filter_map(|stack| stack.last().copied())
+            .collect(),
+        conflicts,
+    })
+}
+
+/// Applies instructions one at a time, keeping the stacks after every step
+/// so the yard can be rewound and replayed.
+pub struct CrateYard<C: Crane> {
+    crane: C,
+    instructions: Vec<Instruction>,
+    history: Vec<Vec<Vec<char>>>,
+    step: usize,
+}
+
+impl<C: Crane> CrateYard<C> {
+    pub fn new(data: &str, crane: C) -> Result<Self> {
+        let (stacks, instructions) = parse(data)?;
+        Ok(Self {
+            crane,
+            instructions,
+            history: vec![stacks],
+            step: 0,
+        })
+    }
+
+    /// Sets up the yard straight from a procedure. Instructions are numbered
+    /// by their position in the procedure, starting at 1.
+    pub fn from_procedure(procedure: &Procedure, crane: C) -> Result<Self> {
+        procedure.check()?;
+        let instructions = procedure
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(index, mv)| Instruction {
+                line: index + 1,
+                amount: mv.amount,
+                from: mv.from - 1,
+                to: mv.to - 1,
+            })
+            .collect();
+        Ok(Self {
+            crane,
+            instructions,
+            history: vec![procedure
+                .stacks
+                .iter()
+                .map(|s| s.chars().collect())
+                .collect()],
+            step: 0,
+        })
+    }
+
+    pub fn step(&mut self) -> Result<bool> {
+        if self.step == self.instructions.len() {
+            return Ok(false);
+        }
+        self.record(self.step + 1)?;
+        self.step += 1;
+        Ok(true)
+    }
+
+    pub fn undo(&mut self) -> bool {
+        if self.step == 0 {
+            return false;
+        }
+        self.step -= 1;
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if self.step + 1 >= self.history.len() {
+            return false;
+        }
+        self.step += 1;
+        true
+    }
+
+    pub fn seek(&mut self, step: usize) -> Result<()> {
+        if step > self.instructions.len() {
+            return Err(format!(
+                "Cannot seek to step {step}, there are only {} instructions!",
+                self.instructions.len()
+            )
+            .into());
+        }
+        self.record(step)?;
+        self.step = step;
+        Ok(())
+    }
+
+    // Works out the stacks up to `step` without moving the yard.
+    fn record(&mut self, step: usize) -> Result<()> {
+        while self.history.len() <= step {
+            let last = self.history.len() - 1;
+            let mut stacks = self.history[last].clone();
+            self.instructions[last].apply(&self.crane, &mut stacks)?;
+            self.history.push(stacks);
+        }
+        Ok(())
+    }
+
+    pub fn position(&self) -> usize {
+        self.step
+    }
+
+    pub fn moves(&self) -> &[Instruction] {
+        &self.instructions[..self.step]
+    }
+
+    pub fn stacks(&self) -> &[Vec<char>] {
+        &self.history[self.step]
+    }
+
+    pub fn stacks_at(&self, step: usize) -> Option<&[Vec<char>]> {
+        self.history.get(step).map(|stacks| stacks.as_slice())
+    }
+
+    pub fn tops(&self) -> Vec<char> {
+        self.stacks()
+            .iter()
+            .filter_map(|stack| stack.last().copied())
+            .collect()
+    }
+
+    /// Renders the yard before the first instruction and after every one.
+    pub fn frames(&mut self) -> Result<Vec<String>> {
+        self.record(self.instructions.len())?;
+        Ok(self.history.iter().map(|stacks| render(stacks)).collect())
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let state = YardState {
+            step: self.step,
+            stacks: self.stacks().iter().map(String::from_iter).collect(),
+            tops: String::from_iter(self.tops()),
+        };
+        Ok(serde_json::to_string(&state)?)
+    }
+}
+
+#[derive(Serialize)]
+struct YardState {
+    step: usize,
+    stacks: Vec<String>,
+    tops: String,
+}
+
+/// The starting stacks and instructions in a form that is easy to generate.
+/// Stacks are listed bottom to top and numbered from one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Procedure {
+    pub stacks: Vec<String>,
+    pub instructions: Vec<Move>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub amount: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+impl Move {
+    fn check(&self, stacks: usize) -> std::result::Result<(), String> {
+        match [self.from, self.to]
+            .into_iter()
+            .find(|&stack| stack == 0 || stack > stacks)
+        {
+            Some(stack) => Err(format!("Stack {stack} does not exist!")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Procedure {
+    pub fn from_text(data: &str) -> Result<Self> {
+        let (stacks, instructions) = parse(data)?;
+        Ok(Self {
+            stacks: stacks.iter().map(String::from_iter).collect(),
+            instructions: instructions
+                .iter()
+                .map(|instruction| Move {
+                    amount: instruction.amount,
+                    from: instruction.from + 1,
+                    to: instruction.to + 1,
+                })
+                .collect(),
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let procedure: Self = serde_json::from_str(json)?;
+        procedure.check()?;
+        Ok(procedure)
+    }
+
+    /// Reads the compact format: the stacks on the first line separated by
+    /// spaces, with a lone `-` for an empty stack, then one `N A B` move per
+    /// line.
+    pub fn from_compact(data: &str) -> Result<Self> {
+        let mut lines = data.lines().enumerate();
+        let (_, stacks) = lines.next().ok_or("The compact procedure is empty!")?;
+        let stacks: Vec<String> = stacks
+            .split_whitespace()
+            .map(|stack| match stack {
+                "-" => Ok(String::new()),
+                _ => check_stack(stack).map(|_| stack.to_owned()),
+            })
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|e| format!("Line 1: {e}"))?;
+        let parse_move = |line: &str| -> std::result::Result<Move, String> {
+            let number = |value: &str| {
+                value
+                    .parse()
+                    .map_err(|e| format!("Bad number '{value}': {e}"))
+            };
+            let [amount, from, to] = line.split_whitespace().collect::<Vec<&str>>()[..] else {
+                return Err(format!("Unknown instruction '{line}'!"));
+            };
+            let mv = Move {
+                amount: number(amount)?,
+                from: number(from)?,
+                to: number(to)?,
+            };
+            mv.check(stacks.len())?;
+            Ok(mv)
+        };
+        let instructions = lines
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| parse_move(line).map_err(|e| format!("Line {}: {e}", index + 1)))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self {
+            stacks,
+            instructions,
+        })
+    }
+
+    fn check(&self) -> Result<()> {
+        for (index, stack) in self.stacks.iter().enumerate() {
+            check_stack(stack).map_err(|e| format!("Stack {}: {e}", index + 1))?;
+        }
+        for (index, mv) in self.instructions.iter().enumerate() {
+            mv.check(self.stacks.len())
+                .map_err(|e| format!("Move {}: {e}", index + 1))?;
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let stacks: Vec<Vec<char>> = self.stacks.iter().map(|s| s.chars().collect()).collect();
+        let moves: String = self
+            .instructions
+            .iter()
+            .map(|mv| format!("move {} from {} to {}\n", mv.amount, mv.from, mv.to))
+            .collect();
+        format!("{}\n{moves}", render(&stacks))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn to_compact(&self) -> String {
+        let stacks: Vec<&str> = self
+            .stacks
+            .iter()
+            .map(|stack| if stack.is_empty() { "-" } else { stack })
+            .collect();
+        let moves: String = self
+            .instructions
+            .iter()
+            .map(|mv| format!("{} {} {}\n", mv.amount, mv.from, mv.to))
+            .collect();
+        format!("{}\n{moves}", stacks.join(" "))
+    }
+}
+
+fn check_stack(stack: &str) -> std::result::Result<(), String> {
+    match stack.chars().find(|c| !c.is_alphabetic()) {
+        Some(other) => Err(format!("'{other}' is not a crate!")),
+        None => Ok(()),
+    }
+}
+
+/// Draws the stacks in the puzzle's format so that `parse_cranes` reads
+/// back the same stacks.
+pub fn render(stacks: &[Vec<char>]) -> String {
+    let height = stacks.iter().map(Vec::len).max().unwrap_or(0);
+    let mut drawing = String::new();
+    for level in (0..height).rev() {
+        let row: Vec<String> = stacks
+            .iter()
+            .map(|stack| match stack.get(level) {
+                Some(letter) => format!("[{letter}]"),
+                None => "   ".to_owned(),
+            })
+            .collect();
+        drawing.push_str(&row.join(" "));
+        drawing.push('\n');
+    }
+    let labels: Vec<String> = (1..=stacks.len())
+        .map(|label| format!("{label:^3}"))
+        .collect();
+    drawing.push_str(&labels.join(" "));
+    drawing.push('\n');
+    drawing
+}
+
+impl<C: Crane + ?Sized> Crane for Box<C> {
+    fn get(&self, start: usize, from_crane: &mut Vec<char>) -> Vec<char> {
+        self.as_ref().get(start, from_crane)
+    }
+
+    fn lifts(&self, amount: usize) -> Result<usize> {
+        self.as_ref().lifts(amount)
+    }
+
+    fn mv(&self, cranes: &mut [Vec<char>], instruction: &[usize]) -> Result<()> {
+        self.as_ref().mv(cranes, instruction)
+    }
+}
+
+impl<C: Crane + ?Sized> Crane for &C {
+    fn get(&self, start: usize, from_crane: &mut Vec<char>) -> Vec<char> {
+        (*self).get(start, from_crane)
+    }
+
+    fn lifts(&self, amount: usize) -> Result<usize> {
+        (*self).lifts(amount)
+    }
+
+    fn mv(&self, cranes: &mut [Vec<char>], instruction: &[usize]) -> Result<()> {
+        (*self).mv(cranes, instruction)
+    }
+}
+
+pub struct CraneV1;
 
 impl Crane for CraneV1 {
     fn get(&self, start: usize, from_crane: &mut Vec<char>) -> Vec<char> {
         from_crane.drain(start..).rev().collect()
     }
+
+    fn lifts(&self, amount: usize) -> Result<usize> {
+        Ok(amount)
+    }
 }
 
-struct CraneV2;
+pub struct CraneV2;
 
 impl Crane for CraneV2 {
     fn get(&self, start: usize, from_crane: &mut Vec<char>) -> Vec<char> {
@@ -52,51 +520,385 @@ impl Crane for CraneV2 {
     }
 }
 
-fn parse_cranes(lines: &mut Lines, num_cranes: usize) -> Vec<Vec<char>> {
-    let mut stacks: Vec<Vec<char>> = vec![vec![]; num_cranes];
-    for line in lines.skip(1) {
-        if line.contains('1') {
-            break;
+/// Lifts at most this many crates at a time, keeping their order within a
+/// lift. A limit of one behaves like `CraneV1` and `usize::MAX` like `CraneV2`.
+pub struct BatchCrane(pub usize);
+
+impl Crane for BatchCrane {
+    fn get(&self, start: usize, from_crane: &mut Vec<char>) -> Vec<char> {
+        let crates: Vec<char> = from_crane.drain(start..).collect();
+        crates.rchunks(self.0.max(1)).flatten().copied().collect()
+    }
+
+    fn lifts(&self, amount: usize) -> Result<usize> {
+        Ok(amount.div_ceil(self.0.max(1)))
+    }
+}
+
+/// Lifts every crate of a move at once like `CraneV2`, refusing moves of
+/// more crates than it can carry.
+pub struct CappedCrane(pub usize);
+
+impl Crane for CappedCrane {
+    fn get(&self, start: usize, from_crane: &mut Vec<char>) -> Vec<char> {
+        from_crane.drain(start..).collect()
+    }
+
+    fn lifts(&self, amount: usize) -> Result<usize> {
+        if amount > self.0 {
+            return Err(format!("Cannot lift {amount} crates, the limit is {}!", self.0).into());
         }
-        let mut chars = line.chars();
-        let mut stack_index = 0;
-        while let (_, Some(letter), _) = (chars.next(), chars.next(), chars.next()) {
-            if (letter).is_alphabetic() {
-                stacks.get_mut(stack_index).unwrap().push(letter);
-            }
-            chars.next();
-            stack_index += 1;
+        Ok(1)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CraneMetrics {
+    pub lifts: usize,
+    pub crates_moved: usize,
+    pub distance: usize,
+}
+
+/// Wraps another crane and counts the work it does. Distance is the number of
+/// stacks travelled by every lift.
+pub struct MeteredCrane<C: Crane> {
+    crane: C,
+    metrics: Cell<CraneMetrics>,
+}
+
+impl<C: Crane> MeteredCrane<C> {
+    pub fn new(crane: C) -> Self {
+        Self {
+            crane,
+            metrics: Cell::default(),
         }
     }
-    for stack in stacks.iter_mut() {
-        stack.reverse();
+
+    pub fn metrics(&self) -> CraneMetrics {
+        self.metrics.get()
+    }
+}
+
+impl<C: Crane> Crane for MeteredCrane<C> {
+    fn get(&self, start: usize, from_crane: &mut Vec<char>) -> Vec<char> {
+        self.crane.get(start, from_crane)
+    }
+
+    fn lifts(&self, amount: usize) -> Result<usize> {
+        self.crane.lifts(amount)
+    }
+
+    fn mv(&self, cranes: &mut [Vec<char>], instruction: &[usize]) -> Result<()> {
+        self.crane.mv(cranes, instruction)?;
+        let &[amount, from, to, ..] = instruction else {
+            return Err("Instruction was in a bad format!".into());
+        };
+        let lifts = self.crane.lifts(amount)?;
+        let mut metrics = self.metrics.get();
+        metrics.lifts += lifts;
+        metrics.crates_moved += amount;
+        metrics.distance += lifts * from.abs_diff(to);
+        self.metrics.set(metrics);
+        Ok(())
     }
-    stacks
 }
 
-fn parse_instructions(lines: &mut Lines) -> Vec<Vec<usize>> {
-    lines
-        .filter(|line| line.chars().next().map(|c| c == 'm').is_some())
-        .fold(Vec::new(), |mut acc, line| {
-            let nums = line
-                .split_whitespace()
-                .filter_map(|s| s.parse::<usize>().ok())
-                .collect::<Vec<usize>>();
-            acc.push(nums);
-            acc
+pub fn parse_cranes(lines: &mut Lines) -> Result<Vec<Vec<char>>> {
+    parse_drawing(lines).map(|(_, stacks)| stacks)
+}
+
+fn parse_drawing(lines: &mut Lines) -> Result<(Vec<usize>, Vec<Vec<char>>)> {
+    let mut rows = Vec::new();
+    let label_row = loop {
+        let line = lines.next().ok_or("Could not find the stack label row!")?;
+        if is_label_row(line) {
+            break line;
+        }
+        rows.push(line);
+    };
+    let positions: Vec<(usize, usize)> = label_row
+        .split_whitespace()
+        .map(|label| {
+            let start = label.as_ptr() as usize - label_row.as_ptr() as usize;
+            (start, start + label.len() - 1)
         })
+        .collect();
+    let width = rows
+        .iter()
+        .chain([&label_row])
+        .map(|row| row.len())
+        .max()
+        .unwrap_or(0);
+    // A trimmed input loses the indentation of its first row. When that row
+    // kept its trailing padding the missing indentation is exactly what it
+    // lacks of the full width. Without padding anywhere it was stripped and
+    // is still aligned on the left, but padding only elsewhere is ambiguous.
+    let short_first = rows
+        .first()
+        .filter(|first| first.starts_with('[') && first.len() < width);
+    let trimmed = match short_first {
+        Some(first) if first.ends_with(char::is_whitespace) => true,
+        Some(_)
+            if rows[1..]
+                .iter()
+                .chain([&label_row])
+                .any(|row| row.ends_with(char::is_whitespace)) =>
+        {
+            return Err("Cannot tell where the first row of the drawing starts!".into());
+        }
+        _ => false,
+    };
+    let mut stacks: Vec<Vec<char>> = vec![vec![]; positions.len()];
+    for (index, row) in rows.iter().enumerate().rev() {
+        let indent = if index == 0 && trimmed {
+            width - row.len()
+        } else {
+            0
+        };
+        for (position, letter) in row.char_indices().filter(|&(_, c)| c.is_alphabetic()) {
+            let position = position + indent;
+            let stack = (0..positions.len())
+                .min_by_key(|&stack| {
+                    let (start, end) = positions[stack];
+                    start.saturating_sub(position) + position.saturating_sub(end)
+                })
+                .ok_or("The stack label row has no labels!")?;
+            stacks[stack].push(letter);
+        }
+    }
+    let labels = label_row
+        .split_whitespace()
+        .map(|label| label.parse())
+        .collect::<std::result::Result<_, _>>()?;
+    Ok((labels, stacks))
+}
+
+fn is_label_row(line: &str) -> bool {
+    !line.trim().is_empty()
+        && line
+            .split_whitespace()
+            .all(|label| label.parse::<usize>().is_ok())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub line: usize,
+    pub amount: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+impl Instruction {
+    fn apply(&self, crane: &impl Crane, stacks: &mut [Vec<char>]) -> Result<()> {
+        crane
+            .mv(stacks, &[self.amount, self.from, self.to])
+            .map_err(|e| format!("Line {}: {e}", self.line).into())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidInstruction {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl Display for InvalidInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.reason)
+    }
+}
+
+impl Error for InvalidInstruction {}
+
+/// The most distinct arrangements `plan` keeps before giving up.
+const PLAN_STATE_LIMIT: usize = 200_000;
+
+/// Searches for the fewest instructions that turn the `start` drawing into
+/// the `target` drawing with the given crane, written in the puzzle's
+/// `move N from A to B` syntax. Gives up with an error once it has seen
+/// `PLAN_STATE_LIMIT` arrangements.
+pub fn plan(start: &str, target: &str, crane: impl Crane) -> Result<String> {
+    plan_within(start, target, crane, PLAN_STATE_LIMIT)
+}
+
+fn plan_within(start: &str, target: &str, crane: impl Crane, limit: usize) -> Result<String> {
+    let (labels, start) = parse_drawing(&mut start.lines())?;
+    let target = parse_cranes(&mut target.lines())?;
+    let mut start_crates: Vec<char> = start.iter().flatten().copied().collect();
+    let mut target_crates: Vec<char> = target.iter().flatten().copied().collect();
+    start_crates.sort_unstable();
+    target_crates.sort_unstable();
+    if start.len() != target.len() || start_crates != target_crates {
+        return Err("The target drawing does not have the same stacks and crates!".into());
+    }
+    // Every stack that differs from the target must be touched at least once
+    // and a move touches two stacks, so this never overestimates.
+    let estimate = |stacks: &[Vec<char>]| {
+        let differing = stacks.iter().zip(&target).filter(|(a, b)| a != b).count();
+        differing.div_ceil(2)
+    };
+    let hash = |stacks: &[Vec<char>]| {
+        let mut hasher = DefaultHasher::new();
+        stacks.hash(&mut hasher);
+        hasher.finish()
+    };
+    // Each arrangement is stored once in `states`; `known` maps its hash to
+    // the ids sharing it, and `fewest`/`parents` are indexed by id.
+    let mut known = HashMap::from([(hash(&start), vec![0])]);
+    let mut queue = BinaryHeap::from([Reverse((estimate(&start), 0, 0))]);
+    let mut states = vec![start];
+    let mut fewest = vec![0];
+    let mut parents: Vec<Option<(usize, [usize; 3])>> = vec![None];
+    while let Some(Reverse((_, moves, id))) = queue.pop() {
+        if states[id] == target {
+            let mut instructions = Vec::new();
+            let mut current = id;
+            while let Some((parent, [amount, from, to])) = parents[current] {
+                instructions.push(format!(
+                    "move {amount} from {} to {}",
+                    labels[from], labels[to]
+                ));
+                current = parent;
+            }
+            instructions.reverse();
+            return Ok(instructions.join("\n"));
+        }
+        if fewest[id] < moves {
+            continue;
+        }
+        for from in 0..states[id].len() {
+            for to in (0..states[id].len()).filter(|&to| to != from) {
+                for amount in 1..=states[id][from].len() {
+                    let mut next = states[id].clone();
+                    if crane.mv(&mut next, &[amount, from, to]).is_err() {
+                        continue;
+                    }
+                    let ids = known.entry(hash(&next)).or_insert_with(Vec::new);
+                    let next_id = match ids.iter().find(|&&other| states[other] == next) {
+                        Some(&other) if fewest[other] <= moves + 1 => continue,
+                        Some(&other) => other,
+                        None if states.len() == limit => {
+                            return Err(format!(
+                                "Gave up after {limit} arrangements without reaching the target drawing!"
+                            )
+                            .into())
+                        }
+                        None => {
+                            ids.push(states.len());
+                            states.push(next);
+                            fewest.push(moves + 1);
+                            parents.push(None);
+                            states.len() - 1
+                        }
+                    };
+                    fewest[next_id] = moves + 1;
+                    parents[next_id] = Some((id, [amount, from, to]));
+                    queue.push(Reverse((
+                        moves + 1 + estimate(&states[next_id]),
+                        moves + 1,
+                        next_id,
+                    )));
+                }
+            }
+        }
+    }
+    Err("The target drawing cannot be reached with this crane!".into())
+}
+
+/// Dry runs the procedure, tracking only stack heights, and lists every
+/// instruction that could not be carried out. Invalid moves are skipped.
+pub fn validate(data: &str) -> Result<Vec<InvalidInstruction>> {
+    let (stacks, instructions) = parse_unchecked(data)?;
+    let mut heights: Vec<usize> = stacks.iter().map(Vec::len).collect();
+    let mut invalid = Vec::new();
+    for instruction in instructions {
+        match instruction {
+            Ok(Instruction {
+                line, amount, from, ..
+            }) if heights[from] < amount => invalid.push(InvalidInstruction {
+                line,
+                reason: format!(
+                    "Cannot move {amount} crates from a stack of {}!",
+                    heights[from]
+                ),
+            }),
+            Ok(Instruction {
+                amount, from, to, ..
+            }) => {
+                heights[from] -= amount;
+                heights[to] += amount;
+            }
+            Err(error) => invalid.push(error),
+        }
+    }
+    Ok(invalid)
+}
+
+fn parse(data: &str) -> Result<(Vec<Vec<char>>, Vec<Instruction>)> {
+    let (stacks, instructions) = parse_unchecked(data)?;
+    let instructions = instructions
         .into_iter()
-        .map(|mut instruction| {
-            instruction[1] -= 1;
-            instruction[2] -= 1;
-            instruction
-        })
-        .collect()
+        .collect::<std::result::Result<_, InvalidInstruction>>()?;
+    Ok((stacks, instructions))
+}
+
+type ParsedInstruction = std::result::Result<Instruction, InvalidInstruction>;
+
+fn parse_unchecked(data: &str) -> Result<(Vec<Vec<char>>, Vec<ParsedInstruction>)> {
+    let mut lines = data.lines();
+    let (labels, stacks) = parse_drawing(&mut lines)?;
+    let first_line = data.lines().count() - lines.clone().count() + 1;
+    let instructions = lines
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| parse_instruction(first_line + index, line, &labels))
+        .collect();
+    Ok((stacks, instructions))
+}
+
+fn parse_instruction(line: usize, text: &str, labels: &[usize]) -> ParsedInstruction {
+    let invalid = |reason: String| InvalidInstruction { line, reason };
+    let [amount, from, to] = match text.split_whitespace().collect::<Vec<&str>>()[..] {
+        ["move", amount, "from", from, "to", to] => [amount, from, to],
+        _ => return Err(invalid(format!("Unknown instruction '{text}'!"))),
+    }
+    .map(|num| {
+        num.parse::<usize>()
+            .map_err(|e| invalid(format!("Bad number '{num}': {e}")))
+    });
+    let stack = |label: usize| {
+        labels
+            .iter()
+            .position(|&existing| existing == label)
+            .ok_or_else(|| invalid(format!("Stack {label} does not exist!")))
+    };
+    Ok(Instruction {
+        line,
+        amount: amount?,
+        from: stack(from?)?,
+        to: stack(to?)?,
+    })
 }
 
 #[cfg(test)]
 mod test {
-    use crate::day5::{execute, CraneV1, CraneV2};
+    use crate::day5::{
+        crane_by_name, execute, execute_parallel, execute_with, parse_cranes, plan, plan_within,
+        render, validate, BatchCrane, CappedCrane, Conflict, ConflictPolicy, Crane, CraneMetrics,
+        CraneV1, CraneV2, CrateYard, Instruction, InvalidInstruction, MeteredCrane, Move,
+        Procedure,
+    };
+
+    const EXAMPLE: &str = r#"
+    [D]    
+[N] [C]    
+[Z] [M] [P]
+ 1   2   3 
+
+move 1 from 2 to 1
+move 3 from 1 to 3
+move 2 from 2 to 1
+move 1 from 1 to 2"#;
 
     #[test]
     fn test_part_one() {
@@ -111,4 +913,370 @@ mod test {
         let result = execute(data, CraneV2).unwrap();
         assert_eq!(String::from_iter(result), "JSDHQMZGF");
     }
+
+    #[test]
+    fn test_example_trimmed() {
+        let data = EXAMPLE.trim();
+        let result = execute(data, CraneV1).unwrap();
+        assert_eq!(String::from_iter(result), "CMZ");
+    }
+
+    #[test]
+    fn parse_cranes_should_detect_wide_drawings() {
+        let data = r#"
+                                            [M]
+[A] [B] [C] [D] [E] [F] [G] [H] [I] [J] [K] [L]
+ 1   2   3   4   5   6   7   8   9  10  11  12 "#;
+        let result = parse_cranes(&mut data.lines()).unwrap();
+        assert_eq!(result.len(), 12);
+        assert_eq!(result[9], vec!['J']);
+        assert_eq!(result[11], vec!['L', 'M']);
+    }
+
+    #[test]
+    fn parse_cranes_should_handle_irregular_spacing() {
+        let data = r#"
+     [B]
+[A]  [C]
+ 1    2"#
+            .trim_start_matches('\n');
+        let result = parse_cranes(&mut data.lines()).unwrap();
+        assert_eq!(result, vec![vec!['A'], vec!['C', 'B']]);
+    }
+
+    #[test]
+    fn parse_cranes_should_keep_an_unpadded_first_row_in_place() {
+        let data = "[A]\n[B] [C]\n 1   2";
+        let result = parse_cranes(&mut data.lines()).unwrap();
+        assert_eq!(result, vec![vec!['B', 'A'], vec!['C']]);
+        let ambiguous = "[A]\n[B] [C] \n 1   2 ";
+        assert!(parse_cranes(&mut ambiguous.lines()).is_err());
+        let data = format!("{data}\n\nmove 1 from 1 to 2");
+        let result = execute(&data, CraneV1).unwrap();
+        assert_eq!(String::from_iter(result), "BA");
+    }
+
+    #[test]
+    fn crate_yard_should_step_through_instructions() {
+        let mut yard = CrateYard::new(EXAMPLE, CraneV1).unwrap();
+        assert!(yard.step().unwrap());
+        assert_eq!(
+            yard.stacks(),
+            &[vec!['Z', 'N', 'D'], vec!['M', 'C'], vec!['P']]
+        );
+        let first = Instruction {
+            line: 7,
+            amount: 1,
+            from: 1,
+            to: 0,
+        };
+        assert_eq!(yard.moves(), &[first]);
+        while yard.step().unwrap() {}
+        assert_eq!(yard.position(), 4);
+        assert_eq!(String::from_iter(yard.tops()), "CMZ");
+    }
+
+    #[test]
+    fn crate_yard_should_rewind_and_replay() {
+        let mut yard = CrateYard::new(EXAMPLE, CraneV1).unwrap();
+        yard.seek(2).unwrap();
+        let after_two = yard.stacks().to_vec();
+        assert!(yard.undo());
+        assert!(yard.undo());
+        assert_eq!(yard.stacks(), yard.stacks_at(0).unwrap());
+        assert!(!yard.undo());
+        assert!(yard.redo());
+        assert!(yard.redo());
+        assert!(!yard.redo());
+        assert_eq!(yard.stacks(), after_two.as_slice());
+        assert!(yard.seek(5).is_err());
+        yard.seek(4).unwrap();
+        assert_eq!(String::from_iter(yard.tops()), "CMZ");
+        // The second instruction moves three crates, more than this crane can.
+        let mut yard = CrateYard::new(EXAMPLE, CappedCrane(2)).unwrap();
+        yard.seek(1).unwrap();
+        assert!(yard.seek(4).is_err());
+        assert_eq!(yard.position(), 1);
+    }
+
+    #[test]
+    fn render_should_match_the_puzzle_drawing() {
+        let stacks = parse_cranes(&mut EXAMPLE.lines()).unwrap();
+        let drawing: String = EXAMPLE
+            .lines()
+            .skip(1)
+            .take(4)
+            .flat_map(|line| [line, "\n"])
+            .collect();
+        assert_eq!(render(&stacks), drawing);
+    }
+
+    #[test]
+    fn render_should_round_trip_through_parse() {
+        let stacks: Vec<Vec<char>> = (0..12)
+            .map(|i| (0..i % 4).map(|h| (b'A' + (i + h) as u8) as char).collect())
+            .collect();
+        let result = parse_cranes(&mut render(&stacks).lines()).unwrap();
+        assert_eq!(result, stacks);
+        // Trimming is only undone when the top row keeps its padding, so
+        // leave the last stack below the top.
+        let stacks = &stacks[..11];
+        let trimmed = render(stacks).trim().to_owned();
+        let result = parse_cranes(&mut trimmed.lines()).unwrap();
+        assert_eq!(result, stacks);
+    }
+
+    #[test]
+    fn crate_yard_should_render_a_frame_per_instruction() {
+        let mut yard = CrateYard::new(EXAMPLE, CraneV2).unwrap();
+        yard.seek(1).unwrap();
+        let frames = yard.frames().unwrap();
+        assert_eq!(frames.len(), 5);
+        assert_eq!(yard.position(), 1);
+        let expected = r#"
+        [D]
+        [N]
+        [Z]
+[M] [C] [P]
+ 1   2   3 
+"#;
+        assert_eq!(frames[4], expected.trim_start_matches('\n'));
+    }
+
+    #[test]
+    fn batch_crane_should_generalise_both_cranes() {
+        let data = include_str!("../resources/day5part1").trim();
+        let one = execute(data, BatchCrane(1)).unwrap();
+        assert_eq!(String::from_iter(one), "FZCMJCRHZ");
+        let all = execute(data, BatchCrane(usize::MAX)).unwrap();
+        assert_eq!(String::from_iter(all), "JSDHQMZGF");
+        let two = execute(EXAMPLE, BatchCrane(2)).unwrap();
+        assert_eq!(String::from_iter(two), "MCZ");
+    }
+
+    #[test]
+    fn capped_crane_should_refuse_heavy_lifts() {
+        assert!(execute(EXAMPLE, CappedCrane(3)).is_ok());
+        let error = execute(EXAMPLE, CappedCrane(2)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Line 8: Cannot lift 3 crates, the limit is 2!"
+        );
+    }
+
+    #[test]
+    fn metered_crane_should_count_work() {
+        let crane = MeteredCrane::new(CraneV1);
+        let mut yard = CrateYard::new(EXAMPLE, &crane).unwrap();
+        while yard.step().unwrap() {}
+        let expected = CraneMetrics {
+            lifts: 7,
+            crates_moved: 7,
+            distance: 10,
+        };
+        assert_eq!(crane.metrics(), expected);
+    }
+
+    #[test]
+    fn crane_by_name_should_select_models() {
+        let tops = |name| String::from_iter(execute_with(EXAMPLE, name).unwrap());
+        assert_eq!(tops("v1"), "CMZ");
+        assert_eq!(tops("v2"), "MCD");
+        assert_eq!(tops("batch:1"), "CMZ");
+        assert_eq!(tops("metered:capped:3"), "MCD");
+        assert!(crane_by_name("v3").is_err());
+        assert!(crane_by_name("batch:x").is_err());
+    }
+
+    #[test]
+    fn execute_should_report_moves_of_missing_crates() {
+        let data = EXAMPLE.replace("move 3 from 1 to 3", "move 4 from 1 to 3");
+        let error = execute(&data, CraneV1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Line 8: Cannot move 4 crates from a stack of 3!"
+        );
+    }
+
+    #[test]
+    fn validate_should_list_every_invalid_instruction() {
+        let data = format!("{EXAMPLE}\nmove 9 from 1 to 2\nmove 1 from 4 to 1\nmove 1 from 0 to 1\njump 1\nmove x from 1 to 2");
+        let result = validate(&data).unwrap();
+        let reasons = [
+            (11, "Cannot move 9 crates from a stack of 1!"),
+            (12, "Stack 4 does not exist!"),
+            (13, "Stack 0 does not exist!"),
+            (14, "Unknown instruction 'jump 1'!"),
+        ];
+        let mut expected: Vec<InvalidInstruction> = reasons
+            .iter()
+            .map(|&(line, reason)| InvalidInstruction {
+                line,
+                reason: reason.to_owned(),
+            })
+            .collect();
+        expected.push(InvalidInstruction {
+            line: 15,
+            reason: "Bad number 'x': invalid digit found in string".to_owned(),
+        });
+        assert_eq!(result, expected);
+        assert!(validate(EXAMPLE).unwrap().is_empty());
+    }
+
+    #[test]
+    fn plan_should_find_shortest_rearrangement() {
+        let start = EXAMPLE.trim_start_matches('\n');
+        let mut yard = CrateYard::new(EXAMPLE, CraneV2).unwrap();
+        yard.seek(4).unwrap();
+        let target = render(yard.stacks());
+        let instructions = plan(start, &target, CraneV2).unwrap();
+        // Z and N, then D, have to be moved onto P, and M has to get out from
+        // under C into stack 1, so four moves is the least possible.
+        assert_eq!(instructions.lines().count(), 4);
+        let procedure = format!("{}\n\n{instructions}", start.split("\n\n").next().unwrap());
+        let tops = execute(&procedure, CraneV2).unwrap();
+        assert_eq!(String::from_iter(tops), "MCD");
+    }
+
+    #[test]
+    fn plan_should_give_up_past_its_state_limit() {
+        let start = EXAMPLE.trim_start_matches('\n');
+        let mut yard = CrateYard::new(EXAMPLE, CraneV2).unwrap();
+        yard.seek(4).unwrap();
+        let target = render(yard.stacks());
+        let error = plan_within(start, &target, CraneV2, 10).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Gave up after 10 arrangements without reaching the target drawing!"
+        );
+    }
+
+    #[test]
+    fn plan_should_respect_the_crane_model() {
+        let start = "[B]        \n[A]        \n 1   2   3 ";
+        let target = "    [B]    \n    [A]    \n 1   2   3 ";
+        assert_eq!(plan(start, target, CraneV2).unwrap(), "move 2 from 1 to 2");
+        assert_eq!(
+            plan(start, target, CraneV1).unwrap(),
+            "move 2 from 1 to 3\nmove 2 from 3 to 2"
+        );
+        let two_stacks = plan(
+            "[B]    \n[A]    \n 1   2 ",
+            "    [B]\n    [A]\n 1   2 ",
+            CraneV1,
+        );
+        assert!(two_stacks.is_err());
+    }
+
+    #[test]
+    fn execute_parallel_should_match_execute_with_one_crane() {
+        let data = include_str!("../resources/day5part1").trim();
+        let result = execute_parallel(data, &[&CraneV1], ConflictPolicy::Flag).unwrap();
+        assert_eq!(String::from_iter(result.tops), "FZCMJCRHZ");
+        assert!(result.conflicts.is_empty());
+    }
+
+    #[test]
+    fn execute_parallel_should_handle_conflicts_by_policy() {
+        let cranes: [&dyn Crane; 2] = [&CraneV1, &CraneV1];
+        let flagged = execute_parallel(EXAMPLE, &cranes, ConflictPolicy::Flag).unwrap();
+        assert_eq!(flagged.makespan, 2);
+        assert_eq!(String::from_iter(&flagged.tops), "CMZ");
+        let serialized = execute_parallel(EXAMPLE, &cranes, ConflictPolicy::Serialize).unwrap();
+        assert_eq!(serialized.makespan, 4);
+        assert_eq!(serialized.tops, execute(EXAMPLE, CraneV1).unwrap());
+        let conflict = |tick, first_line, second_line| Conflict {
+            tick,
+            first_line,
+            second_line,
+        };
+        assert_eq!(
+            flagged.conflicts,
+            vec![conflict(1, 7, 8), conflict(2, 9, 10)]
+        );
+        assert_eq!(
+            serialized.conflicts,
+            vec![conflict(1, 7, 8), conflict(2, 8, 9), conflict(3, 9, 10)]
+        );
+    }
+
+    #[test]
+    fn execute_parallel_should_serialize_like_execute() {
+        let data = include_str!("../resources/day5part1").trim();
+        for count in 2..5 {
+            let cranes = vec![&CraneV2 as &dyn Crane; count];
+            let result = execute_parallel(data, &cranes, ConflictPolicy::Serialize).unwrap();
+            assert_eq!(String::from_iter(result.tops), "JSDHQMZGF");
+        }
+    }
+
+    #[test]
+    fn procedure_should_convert_between_formats() {
+        let procedure = Procedure::from_text(EXAMPLE).unwrap();
+        assert_eq!(procedure.stacks, vec!["ZN", "MCD", "P"]);
+        assert_eq!(
+            procedure.instructions[1],
+            Move {
+                amount: 3,
+                from: 1,
+                to: 3
+            }
+        );
+        let compact = procedure.to_compact();
+        assert_eq!(compact, "ZN MCD P\n1 2 1\n3 1 3\n2 2 1\n1 1 2\n");
+        assert_eq!(Procedure::from_compact(&compact).unwrap(), procedure);
+        let json = procedure.to_json().unwrap();
+        assert_eq!(Procedure::from_json(&json).unwrap(), procedure);
+        assert_eq!(
+            Procedure::from_text(&procedure.to_text()).unwrap(),
+            procedure
+        );
+        let error = Procedure::from_compact("A -\n1 1").unwrap_err();
+        assert_eq!(error.to_string(), "Line 2: Unknown instruction '1 1'!");
+        let error = Procedure::from_compact("A -\n1 1 2\nx 1 2").unwrap_err();
+        assert!(error.to_string().starts_with("Line 3: Bad number 'x': "));
+        let error = Procedure::from_compact("A -\n1 1 3").unwrap_err();
+        assert_eq!(error.to_string(), "Line 2: Stack 3 does not exist!");
+        let json = r#"{"stacks":["A",""],"instructions":[{"amount":1,"from":0,"to":2}]}"#;
+        let error = Procedure::from_json(json).unwrap_err();
+        assert_eq!(error.to_string(), "Move 1: Stack 0 does not exist!");
+        let empty = Procedure::from_compact("A - B\n1 1 2").unwrap();
+        assert_eq!(empty.stacks, vec!["A", "", "B"]);
+        let error = Procedure::from_compact("AB- -\n1 1 2").unwrap_err();
+        assert_eq!(error.to_string(), "Line 1: '-' is not a crate!");
+        let error = Procedure::from_compact("A 1\n1 1 2").unwrap_err();
+        assert_eq!(error.to_string(), "Line 1: '1' is not a crate!");
+        let json = r#"{"stacks":["A","B?"],"instructions":[]}"#;
+        let error = Procedure::from_json(json).unwrap_err();
+        assert_eq!(error.to_string(), "Stack 2: '?' is not a crate!");
+    }
+
+    #[test]
+    fn crate_yard_should_run_a_procedure_directly() {
+        let procedure = Procedure::from_text(EXAMPLE).unwrap();
+        let mut yard = CrateYard::from_procedure(&procedure, CraneV1).unwrap();
+        yard.seek(4).unwrap();
+        assert_eq!(String::from_iter(yard.tops()), "CMZ");
+        let mut invalid = procedure.clone();
+        invalid.instructions[2].from = 4;
+        let error = CrateYard::from_procedure(&invalid, CraneV1).err().unwrap();
+        assert_eq!(error.to_string(), "Move 3: Stack 4 does not exist!");
+        invalid.instructions[2].from = 2;
+        invalid.instructions[2].amount = 9;
+        let mut yard = CrateYard::from_procedure(&invalid, CraneV1).unwrap();
+        let error = yard.seek(3).unwrap_err();
+        assert!(error.to_string().starts_with("Line 3: "));
+    }
+
+    #[test]
+    fn crate_yard_should_export_state_as_json() {
+        let json = r#"{"stacks":["ZN","MCD","P"],"instructions":[{"amount":1,"from":2,"to":1},{"amount":3,"from":1,"to":3},{"amount":2,"from":2,"to":1},{"amount":1,"from":1,"to":2}]}"#;
+        let text = Procedure::from_json(json).unwrap().to_text();
+        let mut yard = CrateYard::new(&text, CraneV1).unwrap();
+        yard.seek(4).unwrap();
+        assert_eq!(
+            yard.to_json().unwrap(),
+            r#"{"step":4,"stacks":["C","M","PDNZ"],"tops":"CMZ"}"#
+        );
+    }
 }
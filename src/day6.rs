@@ -1,3 +1,18 @@
+#[cfg(feature = "grapheme")]
+use unicode_segmentation::UnicodeSegmentation;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    io::{self, BufReader, Read},
+    iter::Copied,
+    slice,
+    str::Chars,
+};
+
+pub const START_OF_PACKET: usize = 4;
+pub const START_OF_MESSAGE: usize = 14;
+
 pub fn protocol(data: &str, num: usize) -> usize {
     let mut seen = Vec::new();
     for (index, c) in data.chars().enumerate() {
@@ -12,9 +27,349 @@ pub fn protocol(data: &str, num: usize) -> usize {
     0
 }
 
+/// Slides a window over a byte stream, keeping a count per byte value so each
+/// byte is handled in constant time.
+pub struct MarkerDetector {
+    size: usize,
+    counts: [usize; 256],
+    distinct: usize,
+    window: VecDeque<u8>,
+    position: usize,
+}
+
+impl MarkerDetector {
+    /// Returns `None` for a `size` of 0, since an empty window cannot be a
+    /// marker.
+    pub fn new(size: usize) -> Option<Self> {
+        (size > 0).then(|| Self::with_size(size))
+    }
+
+    fn with_size(size: usize) -> Self {
+        Self {
+            size,
+            counts: [0; 256],
+            distinct: 0,
+            window: VecDeque::with_capacity(size + 1),
+            position: 0,
+        }
+    }
+
+    /// Adds the next byte, returning whether the last `size` bytes are all
+    /// different.
+    pub fn push(&mut self, byte: u8) -> bool {
+        self.position += 1;
+        self.window.push_back(byte);
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] == 1 {
+            self.distinct += 1;
+        }
+        if self.window.len() > self.size {
+            let old = self.window.pop_front().unwrap() as usize;
+            self.counts[old] -= 1;
+            if self.counts[old] == 0 {
+                self.distinct -= 1;
+            }
+        }
+        self.window.len() == self.size && self.distinct == self.size
+    }
+
+    /// The number of different bytes in the window.
+    pub fn distinct(&self) -> usize {
+        self.distinct
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.window.len() == self.size
+    }
+
+    /// The number of bytes pushed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Forgets the bytes in the window, so the next marker has to be made of
+    /// bytes pushed after this.
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.counts = [0; 256];
+        self.distinct = 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerMode {
+    Overlapping,
+    NonOverlapping,
+}
+
+pub struct Markers<I> {
+    bytes: I,
+    detector: Option<MarkerDetector>,
+    mode: MarkerMode,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Markers<I> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let detector = self.detector.as_mut()?;
+        for byte in self.bytes.by_ref() {
+            if detector.push(byte) {
+                if self.mode == MarkerMode::NonOverlapping {
+                    detector.reset();
+                }
+                return Some(detector.position());
+            }
+        }
+        None
+    }
+}
+
+/// Yields the byte offset just after every window of `size` distinct bytes.
+/// Use `char_markers` to count characters instead. Yields nothing for a
+/// `size` of 0.
+pub fn markers(data: &[u8], size: usize, mode: MarkerMode) -> Markers<Copied<slice::Iter<'_, u8>>> {
+    Markers {
+        bytes: data.iter().copied(),
+        detector: MarkerDetector::new(size),
+        mode,
+    }
+}
+
+pub fn first_marker(data: &[u8], size: usize) -> Option<usize> {
+    markers(data, size, MarkerMode::Overlapping).next()
+}
+
+/// Like `Markers`, but over symbols such as `char`s or grapheme clusters, with
+/// positions counted in symbols.
+pub struct SymbolMarkers<I: Iterator> {
+    symbols: I,
+    size: usize,
+    counts: HashMap<I::Item, usize>,
+    window: VecDeque<I::Item>,
+    position: usize,
+    mode: MarkerMode,
+}
+
+impl<I> Iterator for SymbolMarkers<I>
+where
+    I: Iterator,
+    I::Item: Eq + Hash + Clone,
+{
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.size == 0 {
+            return None;
+        }
+        for symbol in self.symbols.by_ref() {
+            self.position += 1;
+            *self.counts.entry(symbol.clone()).or_insert(0) += 1;
+            self.window.push_back(symbol);
+            if self.window.len() > self.size {
+                let old = self.window.pop_front().unwrap();
+                if let Some(count) = self.counts.get_mut(&old) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.counts.remove(&old);
+                    }
+                }
+            }
+            if self.window.len() == self.size && self.counts.len() == self.size {
+                if self.mode == MarkerMode::NonOverlapping {
+                    self.window.clear();
+                    self.counts.clear();
+                }
+                return Some(self.position);
+            }
+        }
+        None
+    }
+}
+
+fn symbol_markers<I: Iterator>(symbols: I, size: usize, mode: MarkerMode) -> SymbolMarkers<I> {
+    SymbolMarkers {
+        symbols,
+        size,
+        counts: HashMap::new(),
+        window: VecDeque::with_capacity(size + 1),
+        position: 0,
+        mode,
+    }
+}
+
+/// Yields the character index just after every window of `size` distinct
+/// `char`s.
+pub fn char_markers(data: &str, size: usize, mode: MarkerMode) -> SymbolMarkers<Chars<'_>> {
+    symbol_markers(data.chars(), size, mode)
+}
+
+pub fn detect(bytes: impl IntoIterator<Item = u8>, size: usize) -> Option<usize> {
+    let mut detector = MarkerDetector::new(size)?;
+    bytes
+        .into_iter()
+        .find(|&byte| detector.push(byte))
+        .map(|_| detector.position())
+}
+
+pub fn detect_reader(reader: impl Read, size: usize) -> io::Result<Option<usize>> {
+    let mut detector = MarkerDetector::new(size).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "A marker needs at least one byte!",
+        )
+    })?;
+    for byte in BufReader::new(reader).bytes() {
+        if detector.push(byte?) {
+            return Ok(Some(detector.position()));
+        }
+    }
+    Ok(None)
+}
+
+/// Finds the first window of `size` bytes with at least `min_distinct`
+/// different bytes. `protocol` is the case where `min_distinct` is `size`.
+pub fn first_noisy_marker(data: &str, size: usize, min_distinct: usize) -> Option<usize> {
+    let mut detector = MarkerDetector::new(size)?;
+    data.bytes()
+        .find(|&byte| {
+            detector.push(byte);
+            detector.is_full() && detector.distinct() >= min_distinct
+        })
+        .map(|_| detector.position())
+}
+
+/// Finds the first window of `size` bytes where at most `max_duplicates`
+/// bytes repeat one seen earlier in the window.
+pub fn first_marker_with_duplicates(
+    data: &str,
+    size: usize,
+    max_duplicates: usize,
+) -> Option<usize> {
+    first_noisy_marker(data, size, size.saturating_sub(max_duplicates))
+}
+
+/// Scores every full window of `size` bytes by how many different bytes it
+/// holds. The score at index `i` is for the window ending at `i + size`.
+pub fn window_scores(data: &str, size: usize) -> Vec<usize> {
+    let Some(mut detector) = MarkerDetector::new(size) else {
+        return Vec::new();
+    };
+    data.bytes()
+        .filter_map(|byte| {
+            detector.push(byte);
+            detector.is_full().then(|| detector.distinct())
+        })
+        .collect()
+}
+
+/// How a marker's symbols are counted, which also decides the unit of the
+/// position it is found at.
+pub trait MarkerUnit {
+    fn first_marker(data: &str, size: usize) -> Option<usize>;
+}
+
+/// Counts raw UTF-8 bytes, so a multi-byte character is several symbols and
+/// a position may fall inside a character rather than on a `char` boundary.
+pub struct ByteMarker;
+
+impl MarkerUnit for ByteMarker {
+    fn first_marker(data: &str, size: usize) -> Option<usize> {
+        first_marker(data.as_bytes(), size)
+    }
+}
+
+/// Counts `char`s, so positions are character indices like `protocol`.
+pub struct CharMarker;
+
+impl MarkerUnit for CharMarker {
+    fn first_marker(data: &str, size: usize) -> Option<usize> {
+        char_markers(data, size, MarkerMode::Overlapping).next()
+    }
+}
+
+/// Counts extended grapheme clusters, so positions are grapheme indices.
+#[cfg(feature = "grapheme")]
+pub struct GraphemeMarker;
+
+#[cfg(feature = "grapheme")]
+impl MarkerUnit for GraphemeMarker {
+    fn first_marker(data: &str, size: usize) -> Option<usize> {
+        symbol_markers(data.graphemes(true), size, MarkerMode::Overlapping).next()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Packet,
+    Message,
+}
+
+impl FrameKind {
+    fn marker_size(&self) -> usize {
+        match self {
+            FrameKind::Packet => START_OF_PACKET,
+            FrameKind::Message => START_OF_MESSAGE,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub kind: FrameKind,
+    pub offset: usize,
+    pub payload: &'a [u8],
+}
+
+/// Splits a datastream into frames. Each start-of-packet marker starts a
+/// packet and each start-of-message marker starts a message, ending whatever
+/// frame came before it. A start-of-message marker takes the place of any
+/// start-of-packet markers overlapping it. A frame's `offset` is where its payload
+/// starts, just after its marker, and the payload runs up to the next marker.
+pub fn decode(data: &[u8]) -> Vec<Frame<'_>> {
+    let mut packets = MarkerDetector::with_size(START_OF_PACKET);
+    let mut messages = MarkerDetector::with_size(START_OF_MESSAGE);
+    let mut starts: Vec<(FrameKind, usize)> = Vec::new();
+    for &byte in data {
+        let packet = packets.push(byte);
+        if messages.push(byte) {
+            let offset = messages.position();
+            while starts.last().is_some_and(|&(kind, start)| {
+                kind == FrameKind::Packet && start > offset - START_OF_MESSAGE
+            }) {
+                starts.pop();
+            }
+            starts.push((FrameKind::Message, offset));
+            messages.reset();
+            packets.reset();
+        } else if packet {
+            starts.push((FrameKind::Packet, packets.position()));
+            packets.reset();
+        }
+    }
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|(kind, start)| start - kind.marker_size())
+        .chain([data.len()]);
+    starts
+        .iter()
+        .zip(ends)
+        .map(|(&(kind, offset), end)| Frame {
+            kind,
+            offset,
+            payload: &data[offset..end],
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
-    use super::protocol;
+    use super::{
+        char_markers, decode, detect, detect_reader, first_marker, first_marker_with_duplicates,
+        first_noisy_marker, markers, protocol, window_scores, ByteMarker, CharMarker, Frame,
+        FrameKind, MarkerDetector, MarkerMode, MarkerUnit,
+    };
 
     #[test]
     fn test_part_one() {
@@ -29,4 +384,142 @@ mod test {
         let result = protocol(data, 14);
         assert_eq!(result, 2178)
     }
+
+    #[test]
+    fn detect_should_match_protocol() {
+        let data = include_str!("../resources/day6part1").trim();
+        assert_eq!(detect(data.bytes(), 4), Some(1578));
+        assert_eq!(detect(data.bytes(), 14), Some(2178));
+        assert_eq!(
+            detect("mjqjpqmgbljsphdztnvjfqwrcgsmlb".bytes(), 14),
+            Some(19)
+        );
+        assert_eq!(detect("aaaa".bytes(), 2), None);
+    }
+
+    #[test]
+    fn markers_should_reject_an_empty_window() {
+        assert!(MarkerDetector::new(0).is_none());
+        assert_eq!(detect("abcd".bytes(), 0), None);
+        assert!(detect_reader("abcd".as_bytes(), 0).is_err());
+        assert_eq!(first_marker(b"abcd", 0), None);
+        assert_eq!(
+            char_markers("abcd", 0, MarkerMode::Overlapping).next(),
+            None
+        );
+        assert_eq!(CharMarker::first_marker("abcd", 0), None);
+        assert_eq!(first_noisy_marker("abcd", 0, 0), None);
+        assert!(window_scores("abcd", 0).is_empty());
+    }
+
+    #[test]
+    fn detect_reader_should_read_streams() {
+        let data = include_str!("../resources/day6part1").trim();
+        let result = detect_reader(data.as_bytes(), 14).unwrap();
+        assert_eq!(result, Some(2178));
+        let marker_at_end = detect_reader("abcabcd".as_bytes(), 4).unwrap();
+        assert_eq!(marker_at_end, Some(7));
+    }
+
+    #[test]
+    fn markers_should_find_every_marker() {
+        let overlapping: Vec<usize> = markers(b"abcdeaab", 4, MarkerMode::Overlapping).collect();
+        assert_eq!(overlapping, vec![4, 5, 6]);
+        let separate: Vec<usize> = markers(b"abcdeaab", 4, MarkerMode::NonOverlapping).collect();
+        assert_eq!(separate, vec![4]);
+        let separate: Vec<usize> = markers(b"abcdefgh", 4, MarkerMode::NonOverlapping).collect();
+        assert_eq!(separate, vec![4, 8]);
+    }
+
+    #[test]
+    fn char_markers_should_count_chars() {
+        let overlapping: Vec<usize> =
+            char_markers("abcdéaab", 4, MarkerMode::Overlapping).collect();
+        assert_eq!(overlapping, vec![4, 5, 6]);
+        let separate: Vec<usize> =
+            char_markers("abcdéfgh", 4, MarkerMode::NonOverlapping).collect();
+        assert_eq!(separate, vec![4, 8]);
+        // "aé" is three bytes but only two chars.
+        assert_eq!(first_marker("aé".as_bytes(), 3), Some(3));
+        assert_eq!(char_markers("aé", 3, MarkerMode::Overlapping).next(), None);
+    }
+
+    #[test]
+    fn first_marker_should_distinguish_missing_markers() {
+        assert_eq!(first_marker(b"bvwbjplbgvbhsrlpgdmjqwftvncz", 4), Some(5));
+        assert_eq!(first_marker(b"abca", 4), None);
+        assert_eq!(first_marker(b"abcd", 4), Some(4));
+    }
+
+    #[test]
+    fn decode_should_frame_packets_and_messages() {
+        let data = b"aaaabcdxxxxxxxaabcdefghijklmnhelloowwxyzdata";
+        let frame = |kind, offset, payload: &'static [u8]| Frame {
+            kind,
+            offset,
+            payload,
+        };
+        let expected = vec![
+            frame(FrameKind::Packet, 7, b"xxxxxxxa"),
+            frame(FrameKind::Message, 29, b"helloow"),
+            frame(FrameKind::Packet, 40, b"data"),
+        ];
+        assert_eq!(decode(data), expected);
+    }
+
+    #[test]
+    fn decode_should_find_the_puzzle_markers() {
+        let data = include_str!("../resources/day6part1").trim().as_bytes();
+        let frames = decode(data);
+        assert_eq!(frames[0].offset, 1578);
+        let message = frames
+            .iter()
+            .find(|frame| frame.kind == FrameKind::Message)
+            .unwrap();
+        assert_eq!(message.offset, 2178);
+    }
+
+    #[test]
+    fn byte_marker_should_count_bytes() {
+        // 'é' is two bytes, so the marker ends at a byte offset past the char index.
+        assert_eq!(ByteMarker::first_marker("aéaéxyz", 4), Some(7));
+        assert_eq!(ByteMarker::first_marker("ééé", 2), Some(2));
+        let position = ByteMarker::first_marker("aé", 2).unwrap();
+        assert_eq!(position, 2);
+        assert!(!"aé".is_char_boundary(position));
+    }
+
+    #[test]
+    fn char_marker_should_count_chars() {
+        assert_eq!(CharMarker::first_marker("aéaéxyz", 4), Some(6));
+        assert_eq!(CharMarker::first_marker("ééé", 2), None);
+        let data = include_str!("../resources/day6part1").trim();
+        assert_eq!(CharMarker::first_marker(data, 14), Some(2178));
+    }
+
+    #[cfg(feature = "grapheme")]
+    #[test]
+    fn grapheme_marker_should_count_clusters() {
+        use super::GraphemeMarker;
+        // "e\u{301}" is one grapheme made of two chars.
+        let data = "e\u{301}e\u{301}ab";
+        assert_eq!(CharMarker::first_marker(data, 3), Some(5));
+        assert_eq!(GraphemeMarker::first_marker(data, 3), Some(4));
+    }
+
+    #[test]
+    fn first_noisy_marker_should_tolerate_repeats() {
+        let data = "aabbaacdd";
+        assert_eq!(first_noisy_marker(data, 4, 3), Some(7));
+        assert_eq!(first_marker_with_duplicates(data, 4, 1), Some(7));
+        assert_eq!(first_noisy_marker(data, 4, 4), None);
+        let puzzle = include_str!("../resources/day6part1").trim();
+        assert_eq!(first_noisy_marker(puzzle, 14, 14), Some(2178));
+    }
+
+    #[test]
+    fn window_scores_should_count_distinct_bytes() {
+        assert_eq!(window_scores("aabbaacdd", 4), vec![2, 2, 2, 3, 3, 3]);
+        assert!(window_scores("abc", 4).is_empty());
+    }
 }
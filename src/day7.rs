@@ -1,91 +1,729 @@
-use std::collections::HashMap;
+use std::{
+    cell::OnceCell,
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    fmt::{self, Display},
+};
 
-const ROOT: &str = "/";
-const SEPARATOR: &str = "|";
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
-pub fn part_one(data: &str) -> isize {
-    let dirs = parse_to_dirs(data);
-    let sizes = get_path_sizes(&dirs);
+pub fn part_one(data: &str) -> Result<usize> {
+    let tree = FsTree::parse(data)?;
     let space_marker = 100000;
-    sizes
-        .iter()
-        .filter_map(|(_, &v)| (v <= space_marker).then_some(v))
-        .sum::<isize>()
-}
-
-pub fn part_two(data: &str) -> isize {
-    let disk_space: isize = 70000000;
-    let needed_space: isize = 30000000;
-    let dirs = parse_to_dirs(data);
-    let sizes = get_path_sizes(&dirs);
-    let required = needed_space - (disk_space - sizes.get(ROOT).unwrap());
-    sizes
-        .iter()
-        .map(|(_, v)| v)
-        .reduce(|prev, current| {
-            if (current - required).abs() < (prev - required).abs() {
-                current
-            } else {
-                prev
+    Ok(tree
+        .walk()
+        .filter(|&id| tree.node(id).is_dir())
+        .map(|id| tree.size(id))
+        .filter(|&size| size <= space_marker)
+        .sum())
+}
+
+pub fn part_two(data: &str) -> Result<usize> {
+    let tree = FsTree::parse(data)?;
+    let dir = tree
+        .smallest_dir_to_delete(Disk::default())
+        .ok_or("No directory frees enough space!")?;
+    Ok(tree.size(dir))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disk {
+    pub total: usize,
+    pub needed: usize,
+}
+
+impl Default for Disk {
+    fn default() -> Self {
+        Self {
+            total: 70000000,
+            needed: 30000000,
+        }
+    }
+}
+
+pub type NodeId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Dir,
+    File(usize),
+}
+
+#[derive(Debug)]
+pub struct Node {
+    pub name: String,
+    pub kind: NodeKind,
+    pub parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    size: OnceCell<usize>,
+}
+
+impl Node {
+    fn new(name: &str, kind: NodeKind, parent: Option<NodeId>) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind,
+            parent,
+            children: Vec::new(),
+            size: OnceCell::new(),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == NodeKind::Dir
+    }
+}
+
+/// The filesystem rebuilt from a terminal transcript, with every directory
+/// and file stored in one arena and referred to by its index.
+#[derive(Debug)]
+pub struct FsTree {
+    nodes: Vec<Node>,
+}
+
+impl FsTree {
+    pub const ROOT: NodeId = 0;
+
+    pub fn parse(data: &str) -> Result<Self> {
+        let (tree, issues) = Self::build(data);
+        match issues
+            .into_iter()
+            .find(|issue| matches!(issue.kind, IssueKind::BadLine(_)))
+        {
+            Some(issue) => Err(issue.to_string().into()),
+            None => Ok(tree),
+        }
+    }
+
+    fn build(data: &str) -> (Self, Vec<TranscriptIssue>) {
+        let mut tree = Self {
+            nodes: vec![Node::new("/", NodeKind::Dir, None)],
+        };
+        let mut issues = Vec::new();
+        let mut listed = HashSet::new();
+        let mut current = Self::ROOT;
+        for (index, line) in data.lines().enumerate() {
+            let mut report = |kind| {
+                issues.push(TranscriptIssue {
+                    line: index + 1,
+                    kind,
+                })
+            };
+            match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                ["$", "cd", "/"] => current = Self::ROOT,
+                ["$", "cd", ".."] => match tree.nodes[current].parent {
+                    Some(parent) => current = parent,
+                    None => report(IssueKind::AboveRoot),
+                },
+                ["$", "cd", name] => match tree.child(current, name) {
+                    Some(child) if !tree.nodes[child].is_dir() => {
+                        report(IssueKind::NotADirectory(tree.path(child)))
+                    }
+                    Some(child) => current = child,
+                    None => {
+                        report(IssueKind::UnknownDirectory(name.to_owned()));
+                        current = tree.add(current, name, NodeKind::Dir);
+                    }
+                },
+                [] => {}
+                ["$", "ls"] => {
+                    if !listed.insert(current) {
+                        report(IssueKind::RepeatedListing(tree.path(current)));
+                    }
+                }
+                ["$", ..] => report(IssueKind::UnknownCommand(line.to_owned())),
+                ["dir", name] => {
+                    let dir = tree.add(current, name, NodeKind::Dir);
+                    if !tree.nodes[dir].is_dir() {
+                        report(IssueKind::ConflictingKind(tree.path(dir)));
+                    }
+                }
+                [size, name] => {
+                    let Ok(size) = size.parse() else {
+                        report(IssueKind::BadLine(line.to_owned()));
+                        continue;
+                    };
+                    let file = tree.add(current, name, NodeKind::File(size));
+                    match tree.nodes[file].kind {
+                        NodeKind::File(existing) if existing != size => {
+                            report(IssueKind::ConflictingSize {
+                                path: tree.path(file),
+                                first: existing,
+                                second: size,
+                            })
+                        }
+                        NodeKind::File(_) => {}
+                        NodeKind::Dir => report(IssueKind::ConflictingKind(tree.path(file))),
+                    }
+                }
+                _ => report(IssueKind::BadLine(line.to_owned())),
             }
+        }
+        (tree, issues)
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id].children
+    }
+
+    pub fn child(&self, id: NodeId, name: &str) -> Option<NodeId> {
+        self.nodes[id]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.nodes[child].name == name)
+    }
+
+    pub fn path(&self, id: NodeId) -> String {
+        let mut names = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.nodes[current].parent {
+            names.push(self.nodes[current].name.as_str());
+            current = parent;
+        }
+        names.reverse();
+        format!("/{}", names.join("/"))
+    }
+
+    pub fn size(&self, id: NodeId) -> usize {
+        let node = &self.nodes[id];
+        *node.size.get_or_init(|| match node.kind {
+            NodeKind::File(size) => size,
+            NodeKind::Dir => node.children.iter().map(|&child| self.size(child)).sum(),
         })
-        .copied()
-        .unwrap_or(0)
-}
+    }
 
-fn parse_to_dirs(data: &str) -> HashMap<String, isize> {
-    let mut dirs = HashMap::<String, isize>::new();
-    let mut current_dir = vec![ROOT];
-    for line in data.lines() {
-        match line.split_whitespace().collect::<Vec<&str>>()[..] {
-            ["$", "cd", "/"] => {
-                dirs.entry(ROOT.to_owned()).or_insert(0);
+    /// Visits every node depth first, parents before their children.
+    pub fn walk(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack = vec![Self::ROOT];
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            stack.extend(self.nodes[id].children.iter().rev());
+            Some(id)
+        })
+    }
+
+    pub fn required_space(&self, disk: Disk) -> usize {
+        let free = disk.total.saturating_sub(self.size(Self::ROOT));
+        disk.needed.saturating_sub(free)
+    }
+
+    pub fn smallest_dir_to_delete(&self, disk: Disk) -> Option<NodeId> {
+        let required = self.required_space(disk);
+        self.walk()
+            .filter(|&id| self.nodes[id].is_dir() && self.size(id) >= required)
+            .min_by_key(|&id| self.size(id))
+    }
+
+    /// Chooses directories and files, none inside another, that free at
+    /// least the required space while deleting as few bytes as possible.
+    ///
+    /// This is a knapsack over every total below the required space and keeps
+    /// a node index per byte to free, which suits puzzle sized disks but not
+    /// freeing gigabytes.
+    pub fn plan_deletion(&self, disk: Disk) -> Option<Vec<NodeId>> {
+        let required = self.required_space(disk);
+        if required == 0 {
+            return Some(Vec::new());
+        }
+        if required > self.size(Self::ROOT) {
+            return None;
+        }
+        // In pre-order a node's subtree is a contiguous run, so keeping a node
+        // jumps past its subtree while skipping it moves on to the next one.
+        let order: Vec<NodeId> = self.walk().collect();
+        let mut subtree = vec![1; self.nodes.len()];
+        for &id in order.iter().rev() {
+            if let Some(parent) = self.nodes[id].parent {
+                subtree[parent] += subtree[id];
             }
-            ["$", "cd", ".."] => {
-                current_dir.pop();
+        }
+        // Totals below `required` reachable so far. For each total `producer`
+        // is the last node kept on the way to it. A later write only replaces
+        // it with a node whose total joins `reached` no later, so the rest of
+        // the way is always made of nodes placed before it.
+        let mut reached = SizeSet::new(required);
+        reached.insert(0);
+        let mut producer = vec![Self::ROOT; required];
+        let mut pending: BTreeMap<usize, SizeSet> = BTreeMap::new();
+        let mut best: Option<(usize, NodeId, usize)> = None;
+        for (position, &id) in order.iter().enumerate() {
+            if let Some(landed) = pending.remove(&position) {
+                reached.union_with(&landed);
+            }
+            let size = self.size(id);
+            if let Some(base) = reached.first_from(required.saturating_sub(size)) {
+                if best.is_none_or(|(total, _, _)| base + size < total) {
+                    best = Some((base + size, id, base));
+                }
             }
-            ["$", "cd", dir] => {
-                current_dir.push(dir);
-                dirs.entry(current_dir.join(SEPARATOR)).or_insert(0);
+            if size == 0 || size >= required {
+                continue;
             }
-            ["$", "ls"] | ["dir", _] => {}
-            [size, _] => {
-                let current_size = dirs.entry(current_dir.join(SEPARATOR)).or_insert(0);
-                *current_size += size.parse::<isize>().unwrap();
+            let landing = pending
+                .entry(position + subtree[id])
+                .or_insert_with(|| SizeSet::new(required));
+            for word in size / 64..reached.words.len() {
+                let mut fresh =
+                    reached.shifted_word(word, size) & !reached.words[word] & !landing.words[word];
+                while fresh != 0 {
+                    let total = word * 64 + fresh.trailing_zeros() as usize;
+                    landing.insert(total);
+                    producer[total] = id;
+                    fresh &= fresh - 1;
+                }
+            }
+        }
+        let (_, id, mut rest) = best?;
+        let mut chosen = vec![id];
+        while rest > 0 {
+            let id = producer[rest];
+            chosen.push(id);
+            rest -= self.size(id);
+        }
+        chosen.sort_unstable();
+        Some(chosen)
+    }
+
+    /// Draws the filesystem like `tree`, with each entry's size.
+    pub fn tree(&self, options: ReportOptions) -> String {
+        let mut report = format!(
+            "{} ({})\n",
+            self.path(Self::ROOT),
+            options.size(self.size(Self::ROOT))
+        );
+        self.tree_children(Self::ROOT, "", 1, options, &mut report);
+        report
+    }
+
+    fn tree_children(
+        &self,
+        id: NodeId,
+        prefix: &str,
+        depth: usize,
+        options: ReportOptions,
+        report: &mut String,
+    ) {
+        if options.max_depth.is_some_and(|max| depth > max) {
+            return;
+        }
+        let mut children = self.nodes[id].children.clone();
+        children.sort_by(|&a, &b| self.nodes[a].name.cmp(&self.nodes[b].name));
+        for (index, &child) in children.iter().enumerate() {
+            let last = index + 1 == children.len();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            report.push_str(&format!(
+                "{prefix}{branch}{} ({})\n",
+                self.nodes[child].name,
+                options.size(self.size(child))
+            ));
+            let prefix = format!("{prefix}{indent}");
+            self.tree_children(child, &prefix, depth + 1, options, report);
+        }
+    }
+
+    /// Lists directories like `du`, largest first.
+    pub fn du(&self, options: ReportOptions) -> String {
+        let mut dirs: Vec<(usize, String)> = Vec::new();
+        let mut stack = vec![(Self::ROOT, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            if options.max_depth.is_some_and(|max| depth > max) {
+                continue;
             }
-            _ => {}
+            dirs.push((self.size(id), self.path(id)));
+            stack.extend(
+                self.nodes[id]
+                    .children
+                    .iter()
+                    .filter(|&&child| self.nodes[child].is_dir())
+                    .map(|&child| (child, depth + 1)),
+            );
+        }
+        dirs.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        dirs.iter()
+            .map(|(size, path)| format!("{}\t{path}\n", options.size(*size)))
+            .collect()
+    }
+
+    fn add(&mut self, parent: NodeId, name: &str, kind: NodeKind) -> NodeId {
+        if let Some(existing) = self.child(parent, name) {
+            return existing;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(Node::new(name, kind, Some(parent)));
+        self.nodes[parent].children.push(id);
+        id
+    }
+}
+
+// A bitset of the totals below a limit.
+struct SizeSet {
+    limit: usize,
+    words: Vec<u64>,
+}
+
+impl SizeSet {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            words: vec![0; limit.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, size: usize) {
+        self.words[size / 64] |= 1 << (size % 64);
+    }
+
+    fn union_with(&mut self, other: &SizeSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    fn first_from(&self, size: usize) -> Option<usize> {
+        let mut word = size / 64;
+        let mut bits = *self.words.get(word)? & (u64::MAX << (size % 64));
+        while bits == 0 {
+            word += 1;
+            bits = *self.words.get(word)?;
+        }
+        Some(word * 64 + bits.trailing_zeros() as usize)
+    }
+
+    // Word `word` of the set with `shift` added to every total, dropping any
+    // that reach the limit.
+    fn shifted_word(&self, word: usize, shift: usize) -> u64 {
+        let (skip, bits) = (shift / 64, shift % 64);
+        let mut shifted = self.words[word - skip] << bits;
+        if bits > 0 && word > skip {
+            shifted |= self.words[word - skip - 1] >> (64 - bits);
+        }
+        if word == self.words.len() - 1 && !self.limit.is_multiple_of(64) {
+            shifted &= (1 << (self.limit % 64)) - 1;
         }
+        shifted
     }
-    dirs
 }
 
-fn get_path_sizes(dirs: &HashMap<String, isize>) -> HashMap<String, isize> {
-    let mut sizes = HashMap::<String, isize>::new();
-    for (path, size) in dirs {
-        let mut paths = path.split(SEPARATOR).collect::<Vec<&str>>();
-        while !paths.is_empty() {
-            *(sizes.entry(paths.join(SEPARATOR)).or_insert(0)) += size;
-            paths.pop();
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReportOptions {
+    pub max_depth: Option<usize>,
+    pub human_readable: bool,
+}
+
+impl ReportOptions {
+    fn size(&self, bytes: usize) -> String {
+        if self.human_readable {
+            human_size(bytes)
+        } else {
+            bytes.to_string()
         }
     }
-    sizes
+}
+
+/// Formats a size like `du -h`, in powers of 1024 rounded up, with one
+/// decimal place below ten.
+pub fn human_size(bytes: usize) -> String {
+    let round_up = |size: f64| {
+        if size < 10.0 {
+            (size * 10.0).ceil() / 10.0
+        } else {
+            size.ceil()
+        }
+    };
+    let mut size = bytes as f64;
+    let mut units = ["K", "M", "G", "T"].iter();
+    let mut unit = "";
+    while size >= 1024.0 {
+        match units.next() {
+            Some(next) => unit = next,
+            None => break,
+        }
+        size /= 1024.0;
+    }
+    let mut rounded = round_up(size);
+    if rounded >= 1024.0 {
+        if let Some(next) = units.next() {
+            unit = next;
+            rounded = round_up(size / 1024.0);
+        }
+    }
+    if unit.is_empty() {
+        bytes.to_string()
+    } else if rounded < 10.0 {
+        format!("{rounded:.1}{unit}")
+    } else {
+        format!("{rounded:.0}{unit}")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IssueKind {
+    RepeatedListing(String),
+    UnknownDirectory(String),
+    AboveRoot,
+    ConflictingSize {
+        path: String,
+        first: usize,
+        second: usize,
+    },
+    ConflictingKind(String),
+    NotADirectory(String),
+    UnknownCommand(String),
+    BadLine(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TranscriptIssue {
+    pub line: usize,
+    pub kind: IssueKind,
+}
+
+impl Display for TranscriptIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: ", self.line)?;
+        match &self.kind {
+            IssueKind::RepeatedListing(path) => write!(f, "{path} was already listed"),
+            IssueKind::UnknownDirectory(name) => write!(f, "cd into unlisted directory '{name}'"),
+            IssueKind::AboveRoot => write!(f, "cd .. above the root directory"),
+            IssueKind::ConflictingSize {
+                path,
+                first,
+                second,
+            } => write!(f, "{path} was listed as {first} and {second} bytes"),
+            IssueKind::ConflictingKind(path) => {
+                write!(f, "{path} was listed as both a directory and a file")
+            }
+            IssueKind::NotADirectory(path) => write!(f, "cd into file {path}"),
+            IssueKind::UnknownCommand(command) => write!(f, "unknown command '{command}'"),
+            IssueKind::BadLine(line) => write!(f, "could not understand '{line}'"),
+        }
+    }
+}
+
+/// Lists everything in a transcript that does not add up, such as listing a
+/// directory twice or changing into one that was never listed.
+pub fn check(data: &str) -> Vec<TranscriptIssue> {
+    FsTree::build(data).1
 }
 
 #[cfg(test)]
 mod test {
-    use super::{part_one, part_two};
+    use super::{
+        check, human_size, part_one, part_two, Disk, FsTree, IssueKind, ReportOptions,
+        TranscriptIssue,
+    };
+
+    const EXAMPLE: &str = r#"$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k"#;
 
     #[test]
     fn test() {
         let data = include_str!("../resources/day7part1");
-        let result = part_one(data);
+        let result = part_one(data).unwrap();
         assert_eq!(result, 1517599);
     }
 
     #[test]
     fn test_part_two() {
         let data = include_str!("../resources/day7part1");
-        let result = part_two(data);
+        let result = part_two(data).unwrap();
         assert_eq!(result, 2481982);
     }
+
+    #[test]
+    fn fs_tree_should_model_the_example() {
+        let tree = FsTree::parse(EXAMPLE).unwrap();
+        let a = tree.child(FsTree::ROOT, "a").unwrap();
+        let e = tree.child(a, "e").unwrap();
+        assert_eq!(tree.path(e), "/a/e");
+        assert_eq!(tree.size(e), 584);
+        assert_eq!(tree.size(a), 94853);
+        assert_eq!(tree.size(FsTree::ROOT), 48381165);
+        assert_eq!(tree.children(FsTree::ROOT).len(), 4);
+        let names: Vec<&str> = tree.walk().map(|id| tree.node(id).name.as_str()).collect();
+        assert_eq!(names[..5], ["/", "a", "e", "i", "f"]);
+        assert_eq!(part_one(EXAMPLE).unwrap(), 95437);
+    }
+
+    #[test]
+    fn fs_tree_should_allow_any_names() {
+        let tree = FsTree::parse("$ cd /\n$ ls\ndir |\n$ cd |\n$ ls\n10 |").unwrap();
+        let pipe = tree.child(FsTree::ROOT, "|").unwrap();
+        assert_eq!(tree.path(tree.children(pipe)[0]), "/|/|");
+        assert_eq!(tree.size(FsTree::ROOT), 10);
+    }
+
+    #[test]
+    fn check_should_accept_the_example() {
+        assert!(check(EXAMPLE).is_empty());
+        let data = include_str!("../resources/day7part1");
+        assert!(check(data).is_empty());
+    }
+
+    #[test]
+    fn check_should_report_inconsistencies() {
+        let data = r#"$ cd /
+$ ls
+dir a
+10 b
+$ ls
+20 b
+$ cd x
+$ cd ..
+$ cd ..
+$ rm b
+oops
+dir b
+$ ls
+10 a
+$ cd b"#;
+        let issue = |line, kind| TranscriptIssue { line, kind };
+        let expected = vec![
+            issue(5, IssueKind::RepeatedListing("/".to_owned())),
+            issue(
+                6,
+                IssueKind::ConflictingSize {
+                    path: "/b".to_owned(),
+                    first: 10,
+                    second: 20,
+                },
+            ),
+            issue(7, IssueKind::UnknownDirectory("x".to_owned())),
+            issue(9, IssueKind::AboveRoot),
+            issue(10, IssueKind::UnknownCommand("$ rm b".to_owned())),
+            issue(11, IssueKind::BadLine("oops".to_owned())),
+            issue(12, IssueKind::ConflictingKind("/b".to_owned())),
+            issue(13, IssueKind::RepeatedListing("/".to_owned())),
+            issue(14, IssueKind::ConflictingKind("/a".to_owned())),
+            issue(15, IssueKind::NotADirectory("/b".to_owned())),
+        ];
+        let result = check(data);
+        assert_eq!(result, expected);
+        assert_eq!(
+            result[1].to_string(),
+            "Line 6: /b was listed as 10 and 20 bytes"
+        );
+        assert_eq!(
+            result[8].to_string(),
+            "Line 14: /a was listed as both a directory and a file"
+        );
+        let tree = FsTree::parse(&data.replace("oops", "")).unwrap();
+        assert_eq!(tree.size(FsTree::ROOT), 10);
+    }
+
+    #[test]
+    fn tree_should_draw_the_filesystem() {
+        let tree = FsTree::parse(EXAMPLE).unwrap();
+        let expected = r#"
+/ (48381165)
+├── a (94853)
+│   ├── e (584)
+│   │   └── i (584)
+│   ├── f (29116)
+│   ├── g (2557)
+│   └── h.lst (62596)
+├── b.txt (14848514)
+├── c.dat (8504156)
+└── d (24933642)
+    ├── d.ext (5626152)
+    ├── d.log (8033020)
+    ├── j (4060174)
+    └── k (7214296)
+"#;
+        assert_eq!(tree.tree(ReportOptions::default()), expected.trim_start());
+        let options = ReportOptions {
+            max_depth: Some(1),
+            human_readable: true,
+        };
+        let shallow = "/ (47M)\n├── a (93K)\n├── b.txt (15M)\n├── c.dat (8.2M)\n└── d (24M)\n";
+        assert_eq!(tree.tree(options), shallow);
+    }
+
+    #[test]
+    fn du_should_list_directories_by_size() {
+        let tree = FsTree::parse(EXAMPLE).unwrap();
+        let result = tree.du(ReportOptions::default());
+        assert_eq!(result, "48381165\t/\n24933642\t/d\n94853\t/a\n584\t/a/e\n");
+        let options = ReportOptions {
+            max_depth: Some(1),
+            human_readable: true,
+        };
+        // Like du, sizes are rounded up: 48381165 bytes is 46.1M.
+        assert_eq!(tree.du(options), "47M\t/\n24M\t/d\n93K\t/a\n");
+        assert_eq!(human_size(1023), "1023");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(1537), "1.6K");
+        assert_eq!(human_size(10188), "10K");
+        assert_eq!(human_size(1048575), "1.0M");
+    }
+
+    #[test]
+    fn smallest_dir_to_delete_should_free_enough_space() {
+        let tree = FsTree::parse(EXAMPLE).unwrap();
+        let dir = tree.smallest_dir_to_delete(Disk::default()).unwrap();
+        assert_eq!(tree.path(dir), "/d");
+        assert_eq!(tree.size(dir), 24933642);
+        // The closest directory to the 680 bytes needed here is /a/e, which
+        // is too small.
+        let disk = Disk {
+            total: 48381165,
+            needed: 680,
+        };
+        assert_eq!(tree.required_space(disk), 680);
+        let dir = tree.smallest_dir_to_delete(disk).unwrap();
+        assert_eq!(tree.path(dir), "/a");
+    }
+
+    #[test]
+    fn plan_deletion_should_minimise_deleted_bytes() {
+        let tree = FsTree::parse(EXAMPLE).unwrap();
+        let disk = Disk {
+            total: 48381165,
+            needed: 3000,
+        };
+        let plan = tree.plan_deletion(disk).unwrap();
+        let paths: Vec<String> = plan.iter().map(|&id| tree.path(id)).collect();
+        assert_eq!(paths, vec!["/a/e", "/a/g"]);
+        let impossible = Disk {
+            total: 48381165,
+            needed: 48381166,
+        };
+        assert!(tree.required_space(impossible) > tree.size(FsTree::ROOT));
+        assert_eq!(tree.plan_deletion(impossible), None);
+        let data = include_str!("../resources/day7part1");
+        let tree = FsTree::parse(data).unwrap();
+        let plan = tree.plan_deletion(Disk::default()).unwrap();
+        let freed: usize = plan.iter().map(|&id| tree.size(id)).sum();
+        assert_eq!(freed, 2476859);
+    }
 }